@@ -0,0 +1,149 @@
+// Reads the members of the static libraries (ar archives), both GNU and BSD
+// variants.
+
+use std::str;
+
+use error::{Error, Result};
+
+const ARCHIVE_MAGIC: &[u8] = b"!<arch>\n";
+const HEADER_SIZE: usize = 60;
+
+pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(ARCHIVE_MAGIC)
+}
+
+fn archive_error(message: &str, offset: usize) -> Error {
+    Error::WasmParse {
+        message: format!("invalid archive: {}", message),
+        offset,
+    }
+}
+
+fn header_field(header: &[u8], start: usize, end: usize) -> &str {
+    str::from_utf8(&header[start..end]).unwrap_or("").trim_end()
+}
+
+// Returns the name and contents of the archive members, the symbol and
+// long names tables are skipped.
+pub fn read_archive_members(data: &[u8]) -> Result<Vec<(String, &[u8])>> {
+    if !is_archive(data) {
+        return Err(archive_error("bad magic", 0));
+    }
+    let mut members = Vec::new();
+    let mut long_names: &[u8] = &[];
+    let mut offset = ARCHIVE_MAGIC.len();
+    while offset < data.len() {
+        if offset + HEADER_SIZE > data.len() || &data[offset + 58..offset + 60] != b"`\n" {
+            return Err(archive_error("bad member header", offset));
+        }
+        let header = &data[offset..offset + HEADER_SIZE];
+        let size: usize = match header_field(header, 48, 58).parse() {
+            Ok(size) => size,
+            Err(_) => return Err(archive_error("bad member size", offset + 48)),
+        };
+        let content_start = offset + HEADER_SIZE;
+        if content_start + size > data.len() {
+            return Err(archive_error("member is out of bounds", offset));
+        }
+        let mut content = &data[content_start..content_start + size];
+        let name = header_field(header, 0, 16);
+        let name = if name == "/" || name == "/SYM64/" || name.starts_with("__.SYMDEF") {
+            None
+        } else if name == "//" {
+            long_names = content;
+            None
+        } else if let Some(len) = name.strip_prefix("#1/") {
+            // BSD: the name is stored at the beginning of the contents.
+            let len: usize = match len.parse() {
+                Ok(len) if len <= content.len() => len,
+                _ => return Err(archive_error("bad member name", offset)),
+            };
+            let name = String::from_utf8_lossy(&content[..len]);
+            let name = name.trim_end_matches('\0').to_owned();
+            content = &content[len..];
+            Some(name)
+        } else if let Some(name_offset) = name.strip_prefix('/') {
+            // GNU: the name is stored in the long names table.
+            let name_offset: usize = match name_offset.parse() {
+                Ok(name_offset) if name_offset <= long_names.len() => name_offset,
+                _ => return Err(archive_error("bad member name", offset)),
+            };
+            let name = &long_names[name_offset..];
+            let end = name.iter().position(|&c| c == b'\n').unwrap_or(name.len());
+            let name = String::from_utf8_lossy(&name[..end]);
+            Some(name.trim_end_matches('/').to_owned())
+        } else {
+            Some(name.trim_end_matches('/').to_owned())
+        };
+        if let Some(name) = name {
+            members.push((name, content));
+        }
+        // The members are aligned to 2 bytes.
+        offset = content_start + size + (size & 1);
+    }
+    Ok(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(name: &str, content: &[u8]) -> Vec<u8> {
+        let mut data = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            name,
+            0,
+            0,
+            0,
+            644,
+            content.len()
+        )
+        .into_bytes();
+        data.extend_from_slice(content);
+        if content.len() & 1 != 0 {
+            data.push(b'\n');
+        }
+        data
+    }
+
+    #[test]
+    fn gnu_archive() {
+        let mut data = ARCHIVE_MAGIC.to_vec();
+        data.extend(member("/", &[0, 0, 0, 0]));
+        data.extend(member("//", b"a_very_long_member_name.o/\n"));
+        data.extend(member("short.o/", b"abc"));
+        data.extend(member("/0", b"\0asm"));
+        let members = read_archive_members(&data).unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0], (String::from("short.o"), &b"abc"[..]));
+        assert_eq!(
+            members[1],
+            (String::from("a_very_long_member_name.o"), &b"\0asm"[..])
+        );
+    }
+
+    #[test]
+    fn bsd_archive() {
+        let mut data = ARCHIVE_MAGIC.to_vec();
+        data.extend(member("__.SYMDEF", &[0, 0, 0, 0]));
+        data.extend(member("#1/20", b"long_member_name.o\0\0\0asm"));
+        let members = read_archive_members(&data).unwrap();
+        assert_eq!(
+            members,
+            [(String::from("long_member_name.o"), &b"\0asm"[..])]
+        );
+    }
+
+    #[test]
+    fn malformed_archive() {
+        assert!(!is_archive(b"\0asm"));
+        assert!(read_archive_members(b"\0asm").is_err());
+        let mut data = ARCHIVE_MAGIC.to_vec();
+        data.extend(member("a.o/", b"abcd"));
+        data.truncate(data.len() - 1);
+        assert!(read_archive_members(&data).is_err());
+        let mut data = ARCHIVE_MAGIC.to_vec();
+        data.extend(member("/9", b"abcd"));
+        assert!(read_archive_members(&data).is_err());
+    }
+}
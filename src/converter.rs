@@ -0,0 +1,458 @@
+// Converts wasm DWARF information to source maps, the options mirror the
+// command line flags.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::prelude::*;
+
+use archive::{is_archive, read_archive_members};
+use dwarf::{get_combined_debug_loc, get_debug_loc, DebugLocInfo};
+use error::{Error, Result};
+use line_tables::line_tables_only;
+use linking::read_linking;
+use reloc::reloc;
+use to_json::{convert_debug_info_to_json, ColumnZero, SourceMapOptions};
+use wasm_read::{
+    create_debug_file, DebugSections, SectionList, SectionPosition, StripOptions, StripProfile,
+    StripReport,
+};
+
+struct PrefixReplacements {
+    replacements: Vec<(String, String)>,
+}
+
+impl PrefixReplacements {
+    fn replace(&self, path: &str) -> String {
+        let mut result = path.to_owned();
+        for (ref old_prefix, ref new_prefix) in self.replacements.iter() {
+            if path.starts_with(old_prefix) {
+                result = result.split_off(old_prefix.len());
+                result.insert_str(0, new_prefix);
+                return result;
+            }
+        }
+        result
+    }
+
+    fn replace_all(&self, paths: &mut [String]) {
+        for path in paths.iter_mut() {
+            *path = self.replace(path);
+        }
+    }
+}
+
+fn read_source(path: &str) -> Result<String> {
+    let to_error = |error| Error::SourceRead {
+        path: String::from(path),
+        error,
+    };
+    let mut f = File::open(path).map_err(to_error)?;
+    let mut data = Vec::new();
+    f.read_to_end(&mut data).map_err(to_error)?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+fn read_object(wasm: &[u8]) -> Result<DebugSections> {
+    let mut debug_sections = DebugSections::read_sections(wasm)?;
+    reloc(&mut debug_sections)?;
+    Ok(debug_sections)
+}
+
+// Global symbols of the object, with the undefined ones that must be
+// resolved (not weak).
+fn object_symbols(debug_sections: &DebugSections) -> Result<(Vec<String>, Vec<String>)> {
+    let mut defined = Vec::new();
+    let mut undefined = Vec::new();
+    for symbol in read_linking(debug_sections)?.symbols {
+        if symbol.is_local() {
+            continue;
+        }
+        match symbol.name {
+            Some(name) if !symbol.is_undefined() => defined.push(name),
+            Some(name) if !symbol.is_weak() => undefined.push(name),
+            _ => (),
+        }
+    }
+    Ok((defined, undefined))
+}
+
+// Objects in the order the linker places them: the object files in the
+// command line order, the archive members when they are pulled in.
+#[derive(Default)]
+struct ObjectSet {
+    objects: Vec<DebugSections>,
+    defined: HashSet<String>,
+    undefined: HashSet<String>,
+    // Archive members that were not pulled in yet by the symbols they define.
+    lazy: HashMap<String, (usize, usize)>,
+    archives: Vec<Vec<Option<DebugSections>>>,
+}
+
+impl ObjectSet {
+    fn add(&mut self, debug_sections: DebugSections) -> Result<()> {
+        let (defined, undefined) = object_symbols(&debug_sections)?;
+        self.objects.push(debug_sections);
+        for name in defined {
+            self.undefined.remove(&name);
+            self.defined.insert(name);
+        }
+        for name in undefined {
+            if self.defined.contains(&name) {
+                continue;
+            }
+            match self.lazy.get(&name).cloned() {
+                Some(member) => self.fetch(member)?,
+                None => {
+                    self.undefined.insert(name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn add_archive(&mut self, members: Vec<Option<DebugSections>>) -> Result<()> {
+        let archive = self.archives.len();
+        let mut member_symbols = Vec::new();
+        for member in members.iter() {
+            member_symbols.push(object_symbols(member.as_ref().unwrap())?.0);
+        }
+        self.archives.push(members);
+        for (member, defined) in member_symbols.into_iter().enumerate() {
+            for name in defined {
+                if self.undefined.contains(&name) {
+                    self.fetch((archive, member))?;
+                } else if !self.defined.contains(&name) {
+                    self.lazy.entry(name).or_insert((archive, member));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn fetch(&mut self, (archive, member): (usize, usize)) -> Result<()> {
+        match self.archives[archive][member].take() {
+            Some(debug_sections) => self.add(debug_sections),
+            // The member was already pulled in.
+            None => Ok(()),
+        }
+    }
+}
+
+// Usage:
+//
+//     let converter = Converter::new()
+//         .prefix("/home/user/project/", "")
+//         .embed_sources(true)
+//         .source_mapping_url("foo.wasm.map");
+//     let debug_sections = converter.read_debug_sections(&wasm)?;
+//     let di = converter.debug_info(&debug_sections)?;
+//     let source_map = converter.source_map(&di);
+//     let wasm = converter.rewrite_wasm(&wasm)?;
+pub struct Converter {
+    relocation: bool,
+    prefix_replacements: PrefixReplacements,
+    embed_sources: bool,
+    source_map_options: SourceMapOptions,
+    strip_options: StripOptions,
+    line_tables_only: bool,
+    source_mapping_url: Option<String>,
+    external_debug_info: Option<String>,
+    producers: Vec<(String, String, String)>,
+    module_name: Option<String>,
+    build_id: Option<Vec<u8>>,
+    moved_sections: Vec<(String, SectionPosition)>,
+    strict: bool,
+}
+
+impl Default for Converter {
+    fn default() -> Converter {
+        Converter {
+            relocation: false,
+            prefix_replacements: PrefixReplacements {
+                replacements: Vec::new(),
+            },
+            embed_sources: false,
+            source_map_options: SourceMapOptions::default(),
+            strip_options: StripOptions::default(),
+            line_tables_only: false,
+            source_mapping_url: None,
+            external_debug_info: None,
+            producers: Vec::new(),
+            module_name: None,
+            build_id: None,
+            moved_sections: Vec::new(),
+            strict: false,
+        }
+    }
+}
+
+impl Converter {
+    pub fn new() -> Converter {
+        Converter::default()
+    }
+
+    // Applies the relocations of the object file to the debug sections.
+    pub fn relocation(mut self, relocation: bool) -> Converter {
+        self.relocation = relocation;
+        self
+    }
+
+    // Replaces the source filename prefix, the first matching prefix wins.
+    pub fn prefix(mut self, old_prefix: &str, new_prefix: &str) -> Converter {
+        self.prefix_replacements
+            .replacements
+            .push((String::from(old_prefix), String::from(new_prefix)));
+        self
+    }
+
+    // Reads and embeds the source files into the source map.
+    pub fn embed_sources(mut self, embed_sources: bool) -> Converter {
+        self.embed_sources = embed_sources;
+        self
+    }
+
+    pub fn is_stmt_only(mut self, is_stmt_only: bool) -> Converter {
+        self.source_map_options.is_stmt_only = is_stmt_only;
+        self
+    }
+
+    pub fn column_zero(mut self, column_zero: ColumnZero) -> Converter {
+        self.source_map_options.column_zero = column_zero;
+        self
+    }
+
+    // Removes the debug sections from the wasm output, the objects stay
+    // linkable.
+    pub fn strip(mut self, strip: bool) -> Converter {
+        self.strip_options.profile = if strip {
+            Some(StripProfile::DebugOnly)
+        } else {
+            None
+        };
+        self
+    }
+
+    pub fn strip_profile(mut self, profile: StripProfile) -> Converter {
+        self.strip_options.profile = Some(profile);
+        self
+    }
+
+    // Rewrites the DWARF sections of the wasm output to keep only the line
+    // tables and the functions names.
+    pub fn line_tables_only(mut self, line_tables_only: bool) -> Converter {
+        self.line_tables_only = line_tables_only;
+        self
+    }
+
+    // Sets the sourceMappingURL section of the wasm output, the existing
+    // section is replaced.
+    pub fn source_mapping_url(mut self, url: &str) -> Converter {
+        self.source_mapping_url = Some(String::from(url));
+        self
+    }
+
+    // Sets the external_debug_info section of the wasm output, the existing
+    // section is replaced.
+    pub fn external_debug_info(mut self, url: &str) -> Converter {
+        self.external_debug_info = Some(String::from(url));
+        self
+    }
+
+    // Removes the custom sections matching the glob pattern from the wasm
+    // output, e.g. ".debug_*".
+    pub fn remove_sections(mut self, pattern: &str) -> Converter {
+        self.strip_options.remove.push(String::from(pattern));
+        self
+    }
+
+    // Keeps the custom sections matching the glob pattern, even if they are
+    // selected by the strip profile or the removed sections.
+    pub fn keep_sections(mut self, pattern: &str) -> Converter {
+        self.strip_options.keep.push(String::from(pattern));
+        self
+    }
+
+    // Adds the tool to the producers section field of the wasm output.
+    pub fn producer(mut self, field: &str, name: &str, version: &str) -> Converter {
+        self.producers.push((
+            String::from(field),
+            String::from(name),
+            String::from(version),
+        ));
+        self
+    }
+
+    // Sets the module name in the name section of the wasm output.
+    pub fn module_name(mut self, name: &str) -> Converter {
+        self.module_name = Some(String::from(name));
+        self
+    }
+
+    pub fn build_id(mut self, build_id: &[u8]) -> Converter {
+        self.build_id = Some(build_id.to_vec());
+        self
+    }
+
+    // Moves the custom sections matching the glob pattern of the wasm output,
+    // e.g. the "sourceMappingURL" section before the "CODE" section. The
+    // sections are moved after the other rewrites, in the order of the calls.
+    pub fn move_sections(mut self, pattern: &str, position: SectionPosition) -> Converter {
+        self.moved_sections.push((String::from(pattern), position));
+        self
+    }
+
+    // Fails on the modules without code or DWARF information instead of
+    // producing an empty source map.
+    pub fn strict(mut self, strict: bool) -> Converter {
+        self.strict = strict;
+        self
+    }
+
+    pub fn read_debug_sections(&self, wasm: &[u8]) -> Result<DebugSections> {
+        let mut debug_sections = DebugSections::read_sections(wasm)?;
+        if self.relocation {
+            reloc(&mut debug_sections)?;
+        }
+        Ok(debug_sections)
+    }
+
+    // Reads the stripped module together with the debug file split from it.
+    pub fn read_split_debug_sections(
+        &self,
+        wasm: &[u8],
+        debug_wasm: &[u8],
+    ) -> Result<DebugSections> {
+        DebugSections::read_split_sections(wasm, debug_wasm)
+    }
+
+    // Reads the relocatable object files. The archive members are included
+    // only if they define a symbol referenced by the objects read before, as
+    // wasm-ld does. The objects are always relocated.
+    pub fn read_objects(&self, files: &[&[u8]]) -> Result<Vec<DebugSections>> {
+        let mut objects = ObjectSet::default();
+        for &data in files {
+            if !is_archive(data) {
+                objects.add(read_object(data)?)?;
+                continue;
+            }
+            let mut members = Vec::new();
+            for (_, member) in read_archive_members(data)? {
+                if member.starts_with(b"\0asm") {
+                    members.push(Some(read_object(member)?));
+                }
+            }
+            objects.add_archive(members)?;
+        }
+        Ok(objects.objects)
+    }
+
+    // Reads the locations, the source files are read (if requested) before
+    // the prefixes are replaced. Missing code or DWARF information results
+    // in the empty locations and a warning, unless in strict mode.
+    pub fn debug_info(&self, debug_sections: &DebugSections) -> Result<DebugLocInfo> {
+        self.complete_debug_info(debug_sections.has_code, get_debug_loc(debug_sections))
+    }
+
+    // Reads the locations of the objects, as if they were linked into one
+    // module, see get_combined_debug_loc.
+    pub fn combined_debug_info(&self, objects: &[DebugSections]) -> Result<DebugLocInfo> {
+        let has_code = objects.iter().any(|debug_sections| debug_sections.has_code);
+        self.complete_debug_info(has_code, get_combined_debug_loc(objects))
+    }
+
+    fn complete_debug_info(
+        &self,
+        has_code: bool,
+        di: Result<DebugLocInfo>,
+    ) -> Result<DebugLocInfo> {
+        let mut warnings = Vec::new();
+        if !has_code {
+            if self.strict {
+                return Err(Error::NoCode);
+            }
+            warnings.push(Error::NoCode);
+        }
+        let mut di = match di {
+            Ok(di) => di,
+            Err(Error::NoDebugInfo) if !self.strict => {
+                warnings.push(Error::NoDebugInfo);
+                DebugLocInfo::default()
+            }
+            Err(err) => return Err(err),
+        };
+        di.warnings.splice(0..0, warnings);
+
+        if self.embed_sources {
+            let mut sources = Vec::new();
+            for file in di.sources.iter() {
+                sources.push(read_source(file)?);
+            }
+            di.sources_content = Some(sources);
+        }
+
+        self.prefix_replacements.replace_all(&mut di.sources);
+        Ok(di)
+    }
+
+    pub fn source_map(&self, di: &DebugLocInfo) -> String {
+        convert_debug_info_to_json(di, &self.source_map_options).to_string()
+    }
+
+    pub fn rewrite_wasm(&self, wasm: &[u8]) -> Result<Vec<u8>> {
+        Ok(self.rewrite_wasm_with_report(wasm)?.0)
+    }
+
+    // Rewrites the wasm and reports the sections removed by the strip
+    // options.
+    pub fn rewrite_wasm_with_report(&self, wasm: &[u8]) -> Result<(Vec<u8>, StripReport)> {
+        let mut sections = SectionList::parse(wasm)?;
+        let mut report = StripReport::default();
+        if self.line_tables_only {
+            let tables = line_tables_only(&DebugSections::read_sections(wasm)?)?;
+            report = sections.replace_debug_sections(&tables);
+        }
+        let strip_report = sections.strip(&self.strip_options)?;
+        report.removed.extend(strip_report.removed);
+        report.bytes_saved += strip_report.bytes_saved;
+        self.rewrite_sections(&mut sections)?;
+        Ok((sections.encode()?, report))
+    }
+
+    // Splits the module into the stripped wasm output, pointing to the debug
+    // file by the external_debug_info section, and the debug file.
+    pub fn split_debug_info(&self, wasm: &[u8], debug_url: &str) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut sections = SectionList::parse(wasm)?;
+        sections.strip(&StripOptions {
+            profile: Some(StripProfile::DebugOnly),
+            ..StripOptions::default()
+        })?;
+        sections.strip(&self.strip_options)?;
+        self.rewrite_sections(&mut sections)?;
+        sections.set_external_debug_info(debug_url);
+        let stripped = sections.encode()?;
+        let debug_file = create_debug_file(wasm, &stripped)?;
+        Ok((stripped, debug_file))
+    }
+
+    fn rewrite_sections(&self, sections: &mut SectionList) -> Result<()> {
+        for (field, name, version) in self.producers.iter() {
+            sections.add_producer(field, name, version)?;
+        }
+        if let Some(ref module_name) = self.module_name {
+            sections.set_module_name(module_name)?;
+        }
+        if let Some(ref build_id) = self.build_id {
+            sections.set_build_id(build_id);
+        }
+        if let Some(ref url) = self.source_mapping_url {
+            sections.set_source_mapping_url(url);
+        }
+        if let Some(ref url) = self.external_debug_info {
+            sections.set_external_debug_info(url);
+        }
+        for (pattern, position) in self.moved_sections.iter() {
+            sections.move_custom_sections(pattern, position)?;
+        }
+        Ok(())
+    }
+}
@@ -1,129 +1,981 @@
 // Parses DWARF information.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use gimli;
 
-use gimli::{DebugAbbrev, DebugInfo, DebugLine, DebugStr, LittleEndian};
+use gimli::{EndianSlice, LittleEndian, SectionId};
 
-trait Reader: gimli::Reader<Offset = usize> {}
-
-impl<'input, Endian> Reader for gimli::EndianBuf<'input, Endian>
-where
-    Endian: gimli::Endianity,
-{
-}
+pub(crate) type Reader<'a> = EndianSlice<'a, LittleEndian>;
 
+use error::{Error, Result};
+use linking::{read_linking, SymbolKind};
 use wasm_read::DebugSections;
 
-fn to_vec(b: &[u8]) -> Vec<u8> {
-    let mut result = Vec::new();
-    result.extend_from_slice(b);
-    result
-}
-
 pub struct DebugLoc {
     pub address: u64,
     pub source_id: u32,
     pub line: u32,
     pub column: u32,
+    pub name_id: Option<u32>,
+    pub is_stmt: bool,
+    pub basic_block: bool,
+    pub prologue_end: bool,
+    pub epilogue_begin: bool,
+    // The row marks the first address past the end of a sequence.
+    pub end_sequence: bool,
+    pub isa: u64,
+    pub discriminator: u64,
+}
+
+pub struct InlinedCall {
+    pub ranges: Vec<(u64, u64)>,
+    // Nesting level of the call, starting from 0 for the calls made directly
+    // from the function body.
+    pub depth: u32,
+    pub name_id: Option<u32>,
+    pub call_source_id: Option<u32>,
+    pub call_line: u32,
+    pub call_column: u32,
 }
 
+pub struct FunctionInfo {
+    pub ranges: Vec<(u64, u64)>,
+    pub name_id: Option<u32>,
+    pub inlined_calls: Vec<InlinedCall>,
+}
+
+// Counts of the line table sequences that were not included into the
+// locations, e.g. belonging to the functions removed by the linker.
+#[derive(Default)]
+pub struct DiscardedSequences {
+    // Sequences that start at the linker tombstone address (0, -1 or -2).
+    pub tombstone: usize,
+    // Sequences that are not covered by the compilation unit, subprogram
+    // or .debug_aranges ranges.
+    pub outside_ranges: usize,
+}
+
+#[derive(Default)]
 pub struct DebugLocInfo {
     pub sources: Vec<String>,
+    pub names: Vec<String>,
     pub locations: Vec<DebugLoc>,
+    // Read with functions(), the function ranges are updated together.
+    functions: Vec<FunctionInfo>,
+    pub discarded_sequences: DiscardedSequences,
+    pub skipped_units: Vec<SkippedUnit>,
     pub sources_content: Option<Vec<String>>,
+    // Problems that did not prevent the locations from being read.
+    pub warnings: Vec<Error>,
+    // Function ranges sorted by the start address, the end address is
+    // exclusive. Used to find function by the address.
+    function_ranges: Vec<(u64, u64, usize)>,
 }
 
-pub fn get_debug_loc(debug_sections: &DebugSections) -> DebugLocInfo {
-    let mut sources = Vec::new();
-    let mut locations = Vec::new();
-    let mut source_to_id_map: HashMap<u64, usize> = HashMap::new();
-
-    let ref tables = debug_sections.tables;
-    let ref debug_str = DebugStr::new(&tables[&to_vec(b".debug_str")], LittleEndian);
-    let ref debug_abbrev = DebugAbbrev::new(&tables[&to_vec(b".debug_abbrev")], LittleEndian);
-    let ref debug_info = DebugInfo::new(&tables[&to_vec(b".debug_info")], LittleEndian);
-    let ref debug_line = DebugLine::new(&tables[&to_vec(b".debug_line")], LittleEndian);
-
-    let mut iter = debug_info.units();
-    while let Some(unit) = iter.next().unwrap_or(None) {
-        let abbrevs = unit.abbreviations(debug_abbrev).unwrap();
-        let mut cursor = unit.entries(&abbrevs);
-        cursor.next_dfs().expect("???");
-        let root = cursor.current().expect("missing die");
-        let offset = match root.attr_value(gimli::DW_AT_stmt_list).unwrap() {
-            Some(gimli::AttributeValue::DebugLineRef(offset)) => offset,
-            _ => continue,
+impl DebugLocInfo {
+    pub fn functions(&self) -> &[FunctionInfo] {
+        &self.functions
+    }
+
+    // Replaces the functions and rebuilds the index used to find them by
+    // the address.
+    pub fn set_functions(&mut self, functions: Vec<FunctionInfo>) {
+        let mut function_ranges = Vec::new();
+        for (index, function) in functions.iter().enumerate() {
+            for &(begin, end) in function.ranges.iter() {
+                function_ranges.push((begin, end, index));
+            }
+        }
+        function_ranges.sort_by_key(|&(begin, _, _)| begin);
+        self.functions = functions;
+        self.function_ranges = function_ranges;
+    }
+
+    pub fn function_at(&self, address: u64) -> Option<&FunctionInfo> {
+        let index = self
+            .function_ranges
+            .partition_point(|&(begin, _, _)| begin <= address);
+        if index == 0 {
+            return None;
+        }
+        let (_, end, function_index) = self.function_ranges[index - 1];
+        if address < end {
+            Some(&self.functions[function_index])
+        } else {
+            None
+        }
+    }
+
+    // Returns the inlined calls that contain the address, ordered from the
+    // outermost to the innermost.
+    pub fn inline_frames(&self, address: u64) -> Vec<&InlinedCall> {
+        let function = match self.function_at(address) {
+            Some(function) => function,
+            None => return Vec::new(),
         };
-        let comp_dir = root.attr(gimli::DW_AT_comp_dir)
-            .unwrap()
-            .and_then(|attr| attr.string_value(debug_str));
-        let comp_name = root.attr(gimli::DW_AT_name)
-            .unwrap()
-            .and_then(|attr| attr.string_value(debug_str));
-        let program = debug_line.program(offset, unit.address_size(), comp_dir, comp_name);
-        let mut block_start_loc = locations.len();
-        if let Ok(program) = program {
-            let mut rows = program.rows();
-            while let Some((header, row)) = rows.next_row().unwrap() {
-                let pc = debug_sections.code_content as u64 + row.address();
-                let line = row.line().unwrap_or(0);
-                let column = match row.column() {
-                    gimli::ColumnType::Column(column) => column,
-                    gimli::ColumnType::LeftEdge => 0,
-                };
-                let file_index = row.file_index();
-                let source_id = if !source_to_id_map.contains_key(&file_index) {
-                    let file_path: String = if let Some(file) = row.file(header) {
-                        if let Some(directory) = file.directory(header) {
-                            format!(
-                                "{}/{}",
-                                directory.to_string_lossy(),
-                                file.path_name().to_string_lossy()
-                            )
-                        } else {
-                            String::from(file.path_name().to_string_lossy())
+        let mut frames: Vec<&InlinedCall> = function
+            .inlined_calls
+            .iter()
+            .filter(|call| {
+                call.ranges
+                    .iter()
+                    .any(|&(begin, end)| begin <= address && address < end)
+            })
+            .collect();
+        frames.sort_by_key(|call| call.depth);
+        frames
+    }
+}
+
+pub(crate) fn load_section(debug_sections: &DebugSections, id: SectionId) -> Reader<'_> {
+    // Missing sections are treated as empty, e.g. .debug_line_str is only
+    // present in DWARF 5 modules.
+    let data = match debug_sections.tables.get(id.name().as_bytes()) {
+        Some(data) => data.as_slice(),
+        None => &[],
+    };
+    EndianSlice::new(data, LittleEndian)
+}
+
+fn is_absolute_path(path: &str) -> bool {
+    path.starts_with('/')
+        || path.starts_with('\\')
+        || path.get(1..3) == Some(":\\")
+        || path.get(1..3) == Some(":/")
+}
+
+fn join_path(dir: &str, path: &str) -> String {
+    if dir.is_empty() || is_absolute_path(path) {
+        return String::from(path);
+    }
+    if dir.ends_with('/') || dir.ends_with('\\') {
+        format!("{}{}", dir, path)
+    } else {
+        format!("{}/{}", dir, path)
+    }
+}
+
+// Resolves the file entry path. For DWARF 4 the directory index 0 refers to
+// the compilation directory, for DWARF 5 it is stored explicitly in the
+// header. Relative directories are resolved against the compilation
+// directory in both cases.
+fn file_entry_path(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    header: &gimli::LineProgramHeader<Reader>,
+    file: &gimli::FileEntry<Reader>,
+) -> String {
+    let attr_to_string = |attr| {
+        dwarf
+            .attr_string(unit, attr)
+            .map(|s| String::from(s.to_string_lossy()))
+            .ok()
+    };
+    let path_name = attr_to_string(file.path_name()).unwrap_or_default();
+    let mut directory = match file.directory(header) {
+        Some(directory) => attr_to_string(directory).unwrap_or_default(),
+        None => String::new(),
+    };
+    if !is_absolute_path(&directory) {
+        if let Some(ref comp_dir) = unit.comp_dir {
+            directory = join_path(&comp_dir.to_string_lossy(), &directory);
+        }
+    }
+    join_path(&directory, &path_name)
+}
+
+// Units of the .debug_info section by their offset, to follow the
+// references between the units (DW_FORM_ref_addr).
+pub(crate) struct UnitIndex<'a> {
+    units: Vec<gimli::Unit<Reader<'a>>>,
+}
+
+impl<'a> UnitIndex<'a> {
+    // The units that cannot be parsed are skipped.
+    pub(crate) fn new(dwarf: &gimli::Dwarf<Reader<'a>>) -> UnitIndex<'a> {
+        let mut units = Vec::new();
+        let mut headers = dwarf.units();
+        while let Ok(Some(header)) = headers.next() {
+            if let Ok(unit) = dwarf.unit(header) {
+                units.push(unit);
+            }
+        }
+        UnitIndex { units }
+    }
+
+    fn find(
+        &self,
+        offset: gimli::DebugInfoOffset,
+    ) -> Option<(&gimli::Unit<Reader<'a>>, gimli::UnitOffset)> {
+        // The units are sorted by their offsets.
+        let index = self
+            .units
+            .partition_point(|unit| unit.header.debug_info_offset().is_some_and(|o| o <= offset));
+        let unit = self.units.get(index.checked_sub(1)?)?;
+        Some((unit, offset.to_unit_offset(&unit.header)?))
+    }
+}
+
+// Limits the chain of the references followed to find the name, so the
+// cyclic references in the malformed DWARF are not followed forever.
+const MAX_NAME_REFERENCES: u32 = 16;
+
+// Finds the DIE name, following DW_AT_specification and DW_AT_abstract_origin
+// references when the name is not present at the DIE itself.
+pub(crate) fn die_name<'a>(
+    dwarf: &gimli::Dwarf<Reader<'a>>,
+    units: &UnitIndex<'a>,
+    unit: &gimli::Unit<Reader<'a>>,
+    entry: &gimli::DebuggingInformationEntry<Reader<'a>>,
+) -> Option<String> {
+    referenced_die_name(dwarf, units, unit, entry, 0)
+}
+
+fn referenced_die_name<'a>(
+    dwarf: &gimli::Dwarf<Reader<'a>>,
+    units: &UnitIndex<'a>,
+    unit: &gimli::Unit<Reader<'a>>,
+    entry: &gimli::DebuggingInformationEntry<Reader<'a>>,
+    references: u32,
+) -> Option<String> {
+    for attr_name in [gimli::DW_AT_name, gimli::DW_AT_linkage_name].iter() {
+        if let Some(attr) = entry.attr_value(*attr_name) {
+            if let Ok(name) = dwarf.attr_string(unit, attr) {
+                return Some(String::from(name.to_string_lossy()));
+            }
+        }
+    }
+    if references >= MAX_NAME_REFERENCES {
+        return None;
+    }
+    for attr_name in [gimli::DW_AT_specification, gimli::DW_AT_abstract_origin].iter() {
+        match entry.attr_value(*attr_name) {
+            Some(gimli::AttributeValue::UnitRef(offset)) => {
+                if let Ok(origin) = unit.entry(offset) {
+                    return referenced_die_name(dwarf, units, unit, &origin, references + 1);
+                }
+            }
+            // The reference into another unit, e.g. the inlined calls with LTO.
+            Some(gimli::AttributeValue::DebugInfoRef(offset)) => {
+                let (origin_unit, unit_offset) = units.find(offset)?;
+                let origin = origin_unit.entry(unit_offset).ok()?;
+                return referenced_die_name(dwarf, units, origin_unit, &origin, references + 1);
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+fn intern_name(
+    name: String,
+    names: &mut Vec<String>,
+    name_to_id_map: &mut HashMap<String, usize>,
+) -> u32 {
+    let id = *name_to_id_map.entry(name).or_insert_with_key(|name| {
+        names.push(name.clone());
+        names.len() - 1
+    });
+    id as u32
+}
+
+// Collapses "." and ".." components and repeated separators, so the same
+// file referenced from different directories gets the same path.
+fn normalize_path(path: &str) -> String {
+    let is_absolute = path.starts_with('/');
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => match components.last() {
+                Some(&last) if last != ".." => {
+                    components.pop();
+                }
+                _ if is_absolute => {}
+                _ => components.push(component),
+            },
+            _ => components.push(component),
+        }
+    }
+    let normalized = components.join("/");
+    if is_absolute {
+        format!("/{}", normalized)
+    } else {
+        normalized
+    }
+}
+
+// Source files shared by all compilation units.
+#[derive(Default)]
+struct SourceTable {
+    sources: Vec<String>,
+    path_to_id_map: HashMap<String, usize>,
+    // File index to source id map of the current unit.
+    file_to_id_map: HashMap<u64, usize>,
+}
+
+impl SourceTable {
+    fn start_unit(&mut self) {
+        self.file_to_id_map.clear();
+    }
+
+    // Removes the sources added since the table had the `len` sources.
+    fn truncate(&mut self, len: usize) {
+        self.sources.truncate(len);
+        self.path_to_id_map.retain(|_, &mut id| id < len);
+        self.file_to_id_map.retain(|_, &mut id| id < len);
+    }
+
+    fn get_id(
+        &mut self,
+        file_index: u64,
+        dwarf: &gimli::Dwarf<Reader>,
+        unit: &gimli::Unit<Reader>,
+        header: &gimli::LineProgramHeader<Reader>,
+    ) -> u32 {
+        if let Some(&id) = self.file_to_id_map.get(&file_index) {
+            return id as u32;
+        }
+        // The file index is zero-based in DWARF 5 and one-based before,
+        // `header.file()` takes care of the difference.
+        let file_path = match header.file(file_index) {
+            Some(file) => normalize_path(&file_entry_path(dwarf, unit, header, file)),
+            None => String::from("<unknown>"),
+        };
+        let sources = &mut self.sources;
+        let id = *self
+            .path_to_id_map
+            .entry(file_path)
+            .or_insert_with_key(|path| {
+                sources.push(path.clone());
+                sources.len() - 1
+            });
+        self.file_to_id_map.insert(file_index, id);
+        id as u32
+    }
+}
+
+// The linkers replace the addresses of the removed functions with 0, -1 or
+// -2 (the latter is used in .debug_ranges and .debug_loc). The code section
+// starts with the functions count, so 0 is never a valid function address.
+fn is_tombstone_address(address: u64, address_size: u8) -> bool {
+    let max_address = !0u64 >> (64 - 8 * address_size as u32);
+    address == 0 || address >= max_address - 1
+}
+
+// Counts the sequences that gimli skips because they start at the -1 or -2
+// tombstone address. The ones that start at 0 are reported as regular rows.
+fn count_skipped_tombstone_sequences(header: &gimli::LineProgramHeader<Reader>) -> Result<usize> {
+    let address_size = header.address_size();
+    let mut count = 0;
+    let mut sequence_start = true;
+    let mut instructions = header.instructions();
+    while let Some(instruction) = instructions.next_instruction(header)? {
+        match instruction {
+            gimli::LineInstruction::SetAddress(address) => {
+                if sequence_start && address != 0 && is_tombstone_address(address, address_size) {
+                    count += 1;
+                }
+                sequence_start = false;
+            }
+            gimli::LineInstruction::EndSequence => {
+                sequence_start = true;
+            }
+            _ => {}
+        }
+    }
+    Ok(count)
+}
+
+// Returns the DIE address ranges relative to the code section content,
+// skipping the ones that start at the tombstone address.
+pub(crate) fn die_ranges<'a>(
+    dwarf: &gimli::Dwarf<Reader<'a>>,
+    unit: &gimli::Unit<Reader<'a>>,
+    entry: &gimli::DebuggingInformationEntry<Reader<'a>>,
+    code_content: u64,
+) -> Result<Vec<(u64, u64)>> {
+    let address_size = unit.encoding().address_size;
+    let mut ranges = Vec::new();
+    let mut range_iter = dwarf.die_ranges(unit, entry)?;
+    while let Some(range) = range_iter.next()? {
+        if is_tombstone_address(range.begin, address_size) || range.begin >= range.end {
+            continue;
+        }
+        ranges.push((
+            code_content.saturating_add(range.begin),
+            code_content.saturating_add(range.end),
+        ));
+    }
+    Ok(ranges)
+}
+
+fn read_aranges(
+    dwarf: &gimli::Dwarf<Reader>,
+    code_content: u64,
+) -> Result<HashMap<gimli::DebugInfoOffset, Vec<(u64, u64)>>> {
+    let mut aranges: HashMap<gimli::DebugInfoOffset, Vec<(u64, u64)>> = HashMap::new();
+    let mut headers = dwarf.debug_aranges.headers();
+    while let Some(header) = headers.next()? {
+        let address_size = header.encoding().address_size;
+        let ranges = aranges.entry(header.debug_info_offset()).or_default();
+        let mut entries = header.entries();
+        while let Some(entry) = entries.next()? {
+            let range = entry.range();
+            if is_tombstone_address(range.begin, address_size) || range.begin >= range.end {
+                continue;
+            }
+            ranges.push((
+                code_content.saturating_add(range.begin),
+                code_content.saturating_add(range.end),
+            ));
+        }
+    }
+    Ok(aranges)
+}
+
+// Compilation unit that was skipped because of the parsing error.
+pub struct SkippedUnit {
+    // Offset of the unit header in the .debug_info section.
+    pub offset: usize,
+    pub name: Option<String>,
+    pub error: Error,
+}
+
+type ArangesMap = HashMap<gimli::DebugInfoOffset, Vec<(u64, u64)>>;
+
+// Accumulates the information read from the compilation units of one or
+// several modules.
+#[derive(Default)]
+struct DebugLocBuilder {
+    source_table: SourceTable,
+    locations: Vec<DebugLoc>,
+    names: Vec<String>,
+    name_to_id_map: HashMap<String, usize>,
+    functions: Vec<FunctionInfo>,
+    discarded_sequences: DiscardedSequences,
+    skipped_units: Vec<SkippedUnit>,
+    warnings: Vec<Error>,
+}
+
+impl DebugLocBuilder {
+    // Reads all compilation units of the module, the code_content is added
+    // to the DWARF addresses. The units that cannot be parsed are skipped.
+    fn read_units(&mut self, debug_sections: &DebugSections, code_content: u64) -> Result<()> {
+        let dwarf = gimli::Dwarf::load(|id| -> Result<_> { Ok(load_section(debug_sections, id)) })?;
+        // The units are read without aranges if the section is malformed.
+        let aranges = match read_aranges(&dwarf, code_content) {
+            Ok(aranges) => aranges,
+            Err(err) => {
+                self.warnings.push(err);
+                HashMap::new()
+            }
+        };
+
+        let skipped_start = self.skipped_units.len();
+        let mut units = Vec::new();
+        let mut iter = dwarf.units();
+        let mut next_offset = 0;
+        loop {
+            let header = match iter.next() {
+                Ok(Some(header)) => header,
+                Ok(None) => break,
+                Err(err) => {
+                    // The units that follow the broken header cannot be located.
+                    self.skipped_units.push(SkippedUnit {
+                        offset: next_offset,
+                        name: None,
+                        error: Error::from(err),
+                    });
+                    break;
+                }
+            };
+            let offset = header
+                .debug_info_offset()
+                .map(|o| o.0)
+                .unwrap_or(next_offset);
+            next_offset = offset + header.length_including_self();
+            match dwarf.unit(header) {
+                Ok(unit) => units.push(unit),
+                Err(err) => {
+                    self.skipped_units.push(SkippedUnit {
+                        offset,
+                        name: None,
+                        error: Error::from(err),
+                    });
+                }
+            }
+        }
+
+        let units = UnitIndex { units };
+        for unit in units.units.iter() {
+            if let Err(error) = self.read_unit(&dwarf, &units, unit, code_content, &aranges) {
+                self.skipped_units.push(SkippedUnit {
+                    offset: unit.header.debug_info_offset().map_or(0, |o| o.0),
+                    name: unit.name.map(|name| String::from(name.to_string_lossy())),
+                    error,
+                });
+            }
+        }
+        self.skipped_units[skipped_start..].sort_by_key(|unit| unit.offset);
+        Ok(())
+    }
+
+    // Reads the unit, in case of error the locations, functions, sources and
+    // names of the unit are removed.
+    fn read_unit<'a>(
+        &mut self,
+        dwarf: &gimli::Dwarf<Reader<'a>>,
+        units: &UnitIndex<'a>,
+        unit: &gimli::Unit<Reader<'a>>,
+        code_content: u64,
+        aranges: &ArangesMap,
+    ) -> Result<()> {
+        let locations_start = self.locations.len();
+        let functions_start = self.functions.len();
+        let sources_start = self.source_table.sources.len();
+        let names_start = self.names.len();
+        let tombstone = self.discarded_sequences.tombstone;
+        let outside_ranges = self.discarded_sequences.outside_ranges;
+        let result = self.read_unit_data(dwarf, units, unit, code_content, aranges);
+        if result.is_err() {
+            self.locations.truncate(locations_start);
+            self.functions.truncate(functions_start);
+            self.source_table.truncate(sources_start);
+            self.names.truncate(names_start);
+            self.name_to_id_map.retain(|_, &mut id| id < names_start);
+            self.discarded_sequences.tombstone = tombstone;
+            self.discarded_sequences.outside_ranges = outside_ranges;
+        }
+        result
+    }
+
+    fn read_unit_data<'a>(
+        &mut self,
+        dwarf: &gimli::Dwarf<Reader<'a>>,
+        units: &UnitIndex<'a>,
+        unit: &gimli::Unit<Reader<'a>>,
+        code_content: u64,
+        aranges: &ArangesMap,
+    ) -> Result<()> {
+        self.source_table.start_unit();
+
+        let units_functions_start = self.functions.len();
+        // The ranges of the code that is described by the unit.
+        let mut unit_ranges = match unit.header.debug_info_offset() {
+            Some(offset) => aranges.get(&offset).cloned().unwrap_or_default(),
+            None => Vec::new(),
+        };
+
+        let line_program_header = unit.line_program.as_ref().map(|p| p.header().clone());
+
+        // Collecting the functions and their inlined calls. The stack keeps
+        // the DIE depth of the inlined subroutines that are being visited.
+        let mut current_function: Option<(isize, FunctionInfo)> = None;
+        let mut inlined_stack: Vec<isize> = Vec::new();
+        let mut entries = unit.entries();
+        while let Some(entry) = entries.next_dfs()? {
+            let depth = entry.depth();
+            if current_function
+                .as_ref()
+                .is_some_and(|&(function_depth, _)| depth <= function_depth)
+            {
+                self.functions.push(current_function.take().unwrap().1);
+                inlined_stack.clear();
+            }
+            while inlined_stack.last().is_some_and(|&d| depth <= d) {
+                inlined_stack.pop();
+            }
+            match entry.tag() {
+                gimli::DW_TAG_compile_unit | gimli::DW_TAG_partial_unit if depth == 0 => {
+                    unit_ranges.extend(die_ranges(dwarf, unit, entry, code_content)?);
+                }
+                gimli::DW_TAG_subprogram if current_function.is_none() => {
+                    let ranges = die_ranges(dwarf, unit, entry, code_content)?;
+                    if ranges.is_empty() {
+                        // Declarations and abstract instances have no code.
+                        continue;
+                    }
+                    let name_id = die_name(dwarf, units, unit, entry)
+                        .map(|name| intern_name(name, &mut self.names, &mut self.name_to_id_map));
+                    current_function = Some((
+                        depth,
+                        FunctionInfo {
+                            ranges,
+                            name_id,
+                            inlined_calls: Vec::new(),
+                        },
+                    ));
+                }
+                gimli::DW_TAG_inlined_subroutine if current_function.is_some() => {
+                    let ranges = die_ranges(dwarf, unit, entry, code_content)?;
+                    let name_id = die_name(dwarf, units, unit, entry)
+                        .map(|name| intern_name(name, &mut self.names, &mut self.name_to_id_map));
+                    let call_source_id = match (
+                        entry.attr_value(gimli::DW_AT_call_file),
+                        line_program_header.as_ref(),
+                    ) {
+                        (Some(gimli::AttributeValue::FileIndex(file_index)), Some(header)) => {
+                            Some(self.source_table.get_id(file_index, dwarf, unit, header))
                         }
-                    } else {
-                        String::from("<unknown>")
+                        _ => None,
+                    };
+                    let attr_u32 = |name| {
+                        entry
+                            .attr_value(name)
+                            .and_then(|value| value.udata_value())
+                            .unwrap_or(0) as u32
                     };
-                    let index = sources.len();
-                    sources.push(file_path);
-                    source_to_id_map.insert(file_index, index);
-                    index
+                    let call = InlinedCall {
+                        ranges,
+                        depth: inlined_stack.len() as u32,
+                        name_id,
+                        call_source_id,
+                        call_line: attr_u32(gimli::DW_AT_call_line),
+                        call_column: attr_u32(gimli::DW_AT_call_column),
+                    };
+                    current_function
+                        .as_mut()
+                        .unwrap()
+                        .1
+                        .inlined_calls
+                        .push(call);
+                    inlined_stack.push(depth);
+                }
+                _ => {}
+            }
+        }
+        if let Some((_, function)) = current_function {
+            self.functions.push(function);
+        }
+        for function in self.functions[units_functions_start..].iter() {
+            unit_ranges.extend(function.ranges.iter().cloned());
+        }
+
+        let program = match unit.line_program {
+            Some(ref program) => program.clone(),
+            None => return Ok(()),
+        };
+        self.discarded_sequences.tombstone += count_skipped_tombstone_sequences(program.header())?;
+        let address_size = program.header().address_size();
+        let locations = &mut self.locations;
+        let mut block_start_loc = locations.len();
+        let mut rows = program.rows();
+        while let Some((header, row)) = rows.next_row()? {
+            // The 8-byte addresses of memory64 modules can be close to
+            // the address space end.
+            let pc = code_content.wrapping_add(row.address());
+            let line = row.line().map(|line| line.get()).unwrap_or(0);
+            let column = match row.column() {
+                gimli::ColumnType::Column(column) => column.get(),
+                gimli::ColumnType::LeftEdge => 0,
+            };
+            let source_id = self
+                .source_table
+                .get_id(row.file_index(), dwarf, unit, header);
+            let loc = DebugLoc {
+                address: pc,
+                source_id,
+                line: line as u32,
+                column: column as u32,
+                name_id: None,
+                is_stmt: row.is_stmt(),
+                basic_block: row.basic_block(),
+                prologue_end: row.prologue_end(),
+                epilogue_begin: row.epilogue_begin(),
+                end_sequence: row.end_sequence(),
+                isa: row.isa(),
+                discriminator: row.discriminator(),
+            };
+            locations.push(loc);
+            if row.end_sequence() {
+                // Removing sequences of the functions that were discarded by
+                // the linker. If the unit has no ranges information, only the
+                // tombstone address is checked.
+                let start = locations[block_start_loc].address;
+                let relative_start = start.wrapping_sub(code_content);
+                let is_dead = if is_tombstone_address(relative_start, address_size) {
+                    self.discarded_sequences.tombstone += 1;
+                    true
+                } else if !unit_ranges.is_empty()
+                    && !unit_ranges
+                        .iter()
+                        .any(|&(begin, end)| begin <= start && start < end)
+                {
+                    self.discarded_sequences.outside_ranges += 1;
+                    true
                 } else {
-                    *source_to_id_map.get(&file_index).unwrap() as usize
+                    false
                 };
-                let loc = DebugLoc {
-                    address: pc,
-                    source_id: source_id as u32,
-                    line: line as u32,
-                    column: column as u32,
-                };
-                locations.push(loc);
-                if row.end_sequence() {
-                    // Heuristic to remove dead functions.
-                    let block_end_loc = locations.len() - 1;
-                    let fn_size = locations[block_end_loc].address - locations[block_start_loc].address + 1;
-                    let fn_size_field_len = ((fn_size + 1).next_power_of_two().trailing_zeros() + 6) / 7;
-                    if locations[block_start_loc].address <= debug_sections.code_content as u64 + fn_size_field_len as u64 {
-                        locations.drain(block_start_loc..);
+                if is_dead {
+                    locations.drain(block_start_loc..);
+                }
+                block_start_loc = locations.len();
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> DebugLocInfo {
+        let mut locations = self.locations;
+        // The sequence end goes before the row of another sequence that starts
+        // at the same address.
+        locations.sort_by_key(|loc| (loc.address, !loc.end_sequence));
+
+        let mut di = DebugLocInfo {
+            sources: self.source_table.sources,
+            names: self.names,
+            locations,
+            discarded_sequences: self.discarded_sequences,
+            skipped_units: self.skipped_units,
+            warnings: self.warnings,
+            ..DebugLocInfo::default()
+        };
+        di.set_functions(self.functions);
+
+        // Assigning the name of the enclosing function to every location.
+        for i in 0..di.locations.len() {
+            di.locations[i].name_id = di
+                .function_at(di.locations[i].address)
+                .and_then(|function| function.name_id);
+        }
+        di
+    }
+}
+
+// Reads the locations from all compilation units. The units that cannot be
+// parsed are skipped and reported in `skipped_units`.
+pub fn get_debug_loc(debug_sections: &DebugSections) -> Result<DebugLocInfo> {
+    if !debug_sections.tables.contains_key(&b".debug_info"[..]) {
+        return Err(Error::NoDebugInfo);
+    }
+
+    let mut builder = DebugLocBuilder::default();
+    builder.read_units(debug_sections, debug_sections.code_content as u64)?;
+    Ok(builder.finish())
+}
+
+// Size of the unsigned LEB128 encoding of the value.
+fn leb_size(value: usize) -> usize {
+    let mut size = 1;
+    let mut rest = value >> 7;
+    while rest != 0 {
+        size += 1;
+        rest >>= 7;
+    }
+    size
+}
+
+fn imported_functions_count(debug_sections: &DebugSections) -> usize {
+    debug_sections
+        .imports
+        .iter()
+        .filter(|import| import.kind == 0)
+        .count()
+}
+
+fn defined_functions_count(debug_sections: &DebugSections) -> usize {
+    debug_sections.func_offsets.len() - imported_functions_count(debug_sections)
+}
+
+// Size of the `__wasm_call_ctors` function (including its size) that wasm-ld
+// places first in the code section: no locals, a call for every init function
+// and the end. The init functions indices follow the functions that stay
+// imported and `__wasm_call_ctors` itself.
+fn call_ctors_size(objects: &[DebugSections]) -> Result<usize> {
+    let mut linkings = Vec::new();
+    for debug_sections in objects {
+        linkings.push(read_linking(debug_sections)?);
+    }
+    let mut defined = HashSet::new();
+    for linking in linkings.iter() {
+        for symbol in linking.symbols.iter() {
+            if let SymbolKind::Function(_) = symbol.kind {
+                if !symbol.is_undefined() {
+                    defined.extend(symbol.name.as_ref());
+                }
+            }
+        }
+    }
+    let mut imported = HashSet::new();
+    for linking in linkings.iter() {
+        for symbol in linking.symbols.iter() {
+            if let SymbolKind::Function(_) = symbol.kind {
+                match symbol.name {
+                    Some(ref name) if symbol.is_undefined() && !defined.contains(name) => {
+                        imported.insert(name);
                     }
-                    block_start_loc = locations.len();
+                    _ => (),
                 }
             }
         }
+    }
+
+    let mut body_size = 2;
+    let mut first_function_index = imported.len() + 1;
+    for (debug_sections, linking) in objects.iter().zip(linkings.iter()) {
+        let imported_count = imported_functions_count(debug_sections);
+        for init_func in linking.init_funcs.iter() {
+            let index = match linking.symbols.get(init_func.symbol_index as usize) {
+                Some(symbol) => match symbol.kind {
+                    SymbolKind::Function(index) if index as usize >= imported_count => {
+                        first_function_index + index as usize - imported_count
+                    }
+                    _ => first_function_index,
+                },
+                None => first_function_index,
+            };
+            body_size += 1 + leb_size(index);
+        }
+        first_function_index += defined_functions_count(debug_sections);
+    }
+    Ok(leb_size(body_size) + body_size)
+}
 
-        // new unit, new sources
-        source_to_id_map.clear();
+// Reads the locations of the relocatable object files as if they were
+// linked together: the functions of the objects follow each other in the
+// same code section after the `__wasm_call_ctors` function generated by the
+// linker, and the addresses are relative to the section content.
+// The linker can remove, rewrite or add other functions, e.g. with
+// --gc-sections, --compress-relocations or --shared-memory, so the result
+// matches the plain layout only.
+pub fn get_combined_debug_loc(objects: &[DebugSections]) -> Result<DebugLocInfo> {
+    let total_functions_count: usize = objects.iter().map(defined_functions_count).sum();
+
+    let mut builder = DebugLocBuilder::default();
+    let mut has_debug_info = false;
+    let mut next_body_offset = leb_size(total_functions_count + 1) + call_ctors_size(objects)?;
+    for debug_sections in objects {
+        // The DWARF addresses include the object's functions count.
+        let count_size = leb_size(defined_functions_count(debug_sections));
+        if debug_sections.tables.contains_key(&b".debug_info"[..]) {
+            has_debug_info = true;
+            builder.read_units(debug_sections, (next_body_offset - count_size) as u64)?;
+        }
+        if debug_sections.has_code {
+            next_body_offset += debug_sections.code_size - count_size;
+        }
     }
+    if !has_debug_info {
+        return Err(Error::NoDebugInfo);
+    }
+    Ok(builder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{die_name, join_path, normalize_path, UnitIndex};
+    use gimli::write;
+    use gimli::{EndianSlice, LittleEndian};
+    use std::collections::HashMap;
 
-    locations.sort_by(|a, b| a.address.cmp(&b.address));
+    // Writes two units: the first has the named subprogram "foo" and the
+    // subprograms referring to each other, the second has the subprogram
+    // referring to "foo".
+    fn write_units() -> HashMap<gimli::SectionId, Vec<u8>> {
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 4,
+            address_size: 4,
+        };
+        let mut dwarf = write::Dwarf::new();
+        let first = dwarf
+            .units
+            .add(write::Unit::new(encoding, write::LineProgram::none()));
+        let unit = dwarf.units.get_mut(first);
+        let root = unit.root();
+        let foo = unit.add(root, gimli::DW_TAG_subprogram);
+        unit.get_mut(foo).set(
+            gimli::DW_AT_name,
+            write::AttributeValue::String(b"foo".to_vec()),
+        );
+        let a = unit.add(root, gimli::DW_TAG_subprogram);
+        let b = unit.add(root, gimli::DW_TAG_subprogram);
+        unit.get_mut(a).set(
+            gimli::DW_AT_specification,
+            write::AttributeValue::UnitRef(b),
+        );
+        unit.get_mut(b).set(
+            gimli::DW_AT_abstract_origin,
+            write::AttributeValue::UnitRef(a),
+        );
+
+        let second = dwarf
+            .units
+            .add(write::Unit::new(encoding, write::LineProgram::none()));
+        let unit = dwarf.units.get_mut(second);
+        let root = unit.root();
+        let inlined = unit.add(root, gimli::DW_TAG_inlined_subroutine);
+        unit.get_mut(inlined).set(
+            gimli::DW_AT_abstract_origin,
+            write::AttributeValue::DebugInfoRef(write::DebugInfoRef::Entry(first, foo)),
+        );
+
+        let mut sections = write::Sections::new(write::EndianVec::new(LittleEndian));
+        dwarf.write(&mut sections).unwrap();
+        let mut result = HashMap::new();
+        sections
+            .for_each(|id, data| -> gimli::Result<()> {
+                result.insert(id, data.slice().to_vec());
+                Ok(())
+            })
+            .unwrap();
+        result
+    }
+
+    #[test]
+    fn referenced_names() {
+        let sections = write_units();
+        let dwarf = gimli::Dwarf::load(|id| -> gimli::Result<_> {
+            let data = sections.get(&id).map_or(&[][..], |data| &data[..]);
+            Ok(EndianSlice::new(data, LittleEndian))
+        })
+        .unwrap();
+        let units = UnitIndex::new(&dwarf);
+        let mut names = Vec::new();
+        for unit in units.units.iter() {
+            let mut entries = unit.entries();
+            while let Some(entry) = entries.next_dfs().unwrap() {
+                if entry.tag() != gimli::DW_TAG_compile_unit {
+                    names.push(die_name(&dwarf, &units, unit, entry));
+                }
+            }
+        }
+        // The cyclic references have no name.
+        assert_eq!(
+            names,
+            [
+                Some(String::from("foo")),
+                None,
+                None,
+                Some(String::from("foo"))
+            ]
+        );
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(normalize_path("/a/b/../c/./d.c"), "/a/c/d.c");
+        assert_eq!(normalize_path("/a//b/d.c"), "/a/b/d.c");
+        assert_eq!(normalize_path("/../a.c"), "/a.c");
+        assert_eq!(normalize_path("a/../../b/c.c"), "../b/c.c");
+        assert_eq!(normalize_path("../../a.c"), "../../a.c");
+        assert_eq!(normalize_path("./a.c"), "a.c");
+        assert_eq!(normalize_path("a/.."), "");
+    }
 
-    DebugLocInfo {
-        sources,
-        locations,
-        sources_content: None,
+    #[test]
+    fn join() {
+        assert_eq!(join_path("/src", "a.c"), "/src/a.c");
+        assert_eq!(join_path("/src/", "a.c"), "/src/a.c");
+        assert_eq!(join_path("/src", "/usr/a.h"), "/usr/a.h");
+        assert_eq!(join_path("c:\\src", "d:\\a.c"), "d:\\a.c");
+        assert_eq!(join_path("", "a.c"), "a.c");
+        // The same file referenced from the different directories.
+        assert_eq!(
+            normalize_path(&join_path("/src/lib", "../include/a.h")),
+            normalize_path(&join_path("/src", "include/a.h"))
+        );
     }
 }
@@ -0,0 +1,129 @@
+// Errors reported by the wasm and DWARF processing.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+use gimli;
+use wasmparser::BinaryReaderError;
+
+#[derive(Debug)]
+pub enum Error {
+    // The option value cannot be applied to the module, e.g. the section to
+    // move the sections to does not exist.
+    InvalidArgument(String),
+    // The wasm module or its linking/reloc sections cannot be parsed.
+    WasmParse { message: String, offset: usize },
+    // The DWARF sections cannot be parsed.
+    DwarfParse(gimli::Error),
+    // The DWARF sections cannot be rewritten.
+    DwarfWrite(String),
+    // The module has no DWARF information.
+    NoDebugInfo,
+    // The module has no functions.
+    NoCode,
+    // The relocation information is missing or refers to unknown entities.
+    Relocation(String),
+    Io(io::Error),
+    // The input or output file cannot be read or written.
+    FileIo { path: String, error: io::Error },
+    // The source file cannot be read to be embedded into the source map.
+    SourceRead { path: String, error: io::Error },
+    // The debug file was split from a different module.
+    DebugFileMismatch,
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    // Process exit code, so the callers can distinguish the failures.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::InvalidArgument(_) => 2,
+            Error::WasmParse { .. } => 3,
+            Error::DwarfParse(_) => 4,
+            Error::NoDebugInfo => 5,
+            Error::Relocation(_) => 6,
+            Error::Io(_) | Error::FileIo { .. } => 7,
+            Error::SourceRead { .. } => 8,
+            Error::NoCode => 9,
+            Error::DwarfWrite(_) => 10,
+            Error::DebugFileMismatch => 11,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidArgument(ref message) => write!(f, "invalid argument: {}", message),
+            Error::WasmParse {
+                ref message,
+                offset,
+            } => write!(f, "wasm parse error at 0x{:x}: {}", offset, message),
+            Error::DwarfParse(ref err) => write!(f, "DWARF parse error: {}", err),
+            Error::DwarfWrite(ref message) => write!(f, "DWARF write error: {}", message),
+            Error::NoDebugInfo => write!(f, "no DWARF debug information found"),
+            Error::NoCode => write!(f, "no code section found"),
+            Error::DebugFileMismatch => write!(f, "debug file does not match the module code"),
+            Error::Relocation(ref message) => write!(f, "relocation error: {}", message),
+            Error::Io(ref err) => write!(f, "I/O error: {}", err),
+            Error::FileIo {
+                ref path,
+                ref error,
+            } => write!(f, "I/O error: {}: {}", path, error),
+            Error::SourceRead {
+                ref path,
+                ref error,
+            } => write!(f, "cannot read source file {}: {}", path, error),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::DwarfParse(ref err) => Some(err),
+            Error::Io(ref err) => Some(err),
+            Error::FileIo { ref error, .. } | Error::SourceRead { ref error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<BinaryReaderError> for Error {
+    fn from(err: BinaryReaderError) -> Error {
+        Error::WasmParse {
+            message: String::from(err.message),
+            offset: err.offset,
+        }
+    }
+}
+
+impl From<gimli::Error> for Error {
+    fn from(err: gimli::Error) -> Error {
+        Error::DwarfParse(err)
+    }
+}
+
+impl From<gimli::write::ConvertError> for Error {
+    fn from(err: gimli::write::ConvertError) -> Error {
+        match err {
+            gimli::write::ConvertError::Read(err) => Error::DwarfParse(err),
+            err => Error::DwarfWrite(err.to_string()),
+        }
+    }
+}
+
+impl From<gimli::write::Error> for Error {
+    fn from(err: gimli::write::Error) -> Error {
+        Error::DwarfWrite(err.to_string())
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,33 @@
+// Reads DWARF information from the wasm files and converts it to source maps.
+
+extern crate gimli;
+extern crate rustc_serialize;
+extern crate vlq;
+extern crate wasmparser;
+
+pub mod archive;
+pub mod converter;
+pub mod dwarf;
+pub mod error;
+pub mod line_tables;
+pub mod linking;
+pub mod lookup;
+pub mod reloc;
+pub mod to_json;
+pub mod wasm_read;
+
+pub use archive::{is_archive, read_archive_members};
+pub use converter::Converter;
+pub use dwarf::{get_combined_debug_loc, get_debug_loc, DebugLoc, DebugLocInfo};
+pub use error::{Error, Result};
+pub use line_tables::line_tables_only;
+pub use linking::{read_linking, LinkingInfo, Symbol, SymbolKind};
+pub use lookup::{find_line_addresses, AddressIndex};
+pub use reloc::{reloc, RelocEntry};
+pub use to_json::{
+    convert_debug_info_to_json, convert_relocations_to_json, ColumnZero, SourceMapOptions,
+};
+pub use wasm_read::{
+    create_debug_file, glob_match, remove_debug_sections, DebugSections, RemovedSection, Section,
+    SectionList, SectionPosition, StripOptions, StripProfile, StripReport,
+};
@@ -0,0 +1,210 @@
+// Rewrites the DWARF sections to keep only the line tables, as produced by
+// clang -gline-tables-only: the compilation units keep the line programs,
+// and only the DIEs of the functions with code and their inlined calls are
+// kept to name them. The types, variables and locations are dropped.
+
+use std::collections::{HashMap, HashSet};
+
+use gimli::write::{self, Address, ConvertUnit, ConvertUnitEntry, EndianVec, Sections};
+use gimli::{LittleEndian, UnitOffset};
+
+use dwarf::{die_name, die_ranges, load_section, Reader, UnitIndex};
+use error::{Error, Result};
+use wasm_read::DebugSections;
+
+fn is_kept_tag(tag: gimli::DwTag) -> bool {
+    matches!(
+        tag,
+        gimli::DW_TAG_namespace
+            | gimli::DW_TAG_subprogram
+            | gimli::DW_TAG_inlined_subroutine
+            | gimli::DW_TAG_lexical_block
+    )
+}
+
+fn is_kept_attribute(name: gimli::DwAt) -> bool {
+    matches!(
+        name,
+        gimli::DW_AT_name
+            | gimli::DW_AT_linkage_name
+            | gimli::DW_AT_producer
+            | gimli::DW_AT_language
+            | gimli::DW_AT_comp_dir
+            | gimli::DW_AT_stmt_list
+            | gimli::DW_AT_low_pc
+            | gimli::DW_AT_high_pc
+            | gimli::DW_AT_ranges
+            | gimli::DW_AT_specification
+            | gimli::DW_AT_abstract_origin
+            | gimli::DW_AT_inline
+            | gimli::DW_AT_external
+            | gimli::DW_AT_decl_file
+            | gimli::DW_AT_decl_line
+            | gimli::DW_AT_call_file
+            | gimli::DW_AT_call_line
+            | gimli::DW_AT_call_column
+    )
+}
+
+struct EntryInfo {
+    offset: UnitOffset,
+    parent: Option<usize>,
+    // The DIE can be kept: its tag and its parents tags are kept.
+    is_candidate: bool,
+    // The DIE is in the subprogram with code.
+    is_live: bool,
+    references: Vec<UnitOffset>,
+}
+
+// Finds the DIEs to keep: the subprograms with code, their inlined
+// subroutines and lexical blocks, the DIEs they refer to (e.g. abstract
+// origins of the inlined subroutines) and the parents of all of them.
+fn kept_entries(unit: gimli::UnitRef<Reader>) -> Result<HashSet<UnitOffset>> {
+    let mut infos: Vec<EntryInfo> = Vec::new();
+    let mut indices = HashMap::new();
+    let mut parents: Vec<(isize, usize)> = Vec::new();
+    let mut entries = unit.entries();
+    while let Some(entry) = entries.next_dfs()? {
+        let depth = entry.depth();
+        while parents.last().is_some_and(|&(d, _)| d >= depth) {
+            parents.pop();
+        }
+        let parent = parents.last().map(|&(_, index)| index);
+        let (is_parent_candidate, is_parent_live) = match parent {
+            Some(index) => (infos[index].is_candidate, infos[index].is_live),
+            None => (true, false),
+        };
+        let is_candidate = is_parent_candidate && (depth == 0 || is_kept_tag(entry.tag()));
+        let is_live = is_candidate
+            && (is_parent_live
+                || (entry.tag() == gimli::DW_TAG_subprogram
+                    && !die_ranges(unit.dwarf, unit.unit, entry, 0)?.is_empty()));
+        let mut references = Vec::new();
+        for attr_name in [gimli::DW_AT_specification, gimli::DW_AT_abstract_origin].iter() {
+            if let Some(gimli::AttributeValue::UnitRef(offset)) = entry.attr_value(*attr_name) {
+                references.push(offset);
+            }
+        }
+        indices.insert(entry.offset(), infos.len());
+        parents.push((depth, infos.len()));
+        infos.push(EntryInfo {
+            offset: entry.offset(),
+            parent,
+            is_candidate,
+            is_live,
+            references,
+        });
+    }
+
+    let mut kept = HashSet::new();
+    let mut queue: Vec<usize> = (0..infos.len())
+        .filter(|&index| index == 0 || infos[index].is_live)
+        .collect();
+    while let Some(index) = queue.pop() {
+        let info = &infos[index];
+        if !info.is_candidate || !kept.insert(info.offset) {
+            continue;
+        }
+        queue.extend(info.parent);
+        queue.extend(
+            info.references
+                .iter()
+                .filter_map(|offset| indices.get(offset).cloned()),
+        );
+    }
+    Ok(kept)
+}
+
+fn convert_attributes<'a>(
+    units: &UnitIndex<'a>,
+    unit: &mut ConvertUnit<Reader<'a>>,
+    id: write::UnitEntryId,
+    entry: &ConvertUnitEntry<Reader<'a>>,
+    kept: &HashSet<UnitOffset>,
+    convert_address: &dyn Fn(u64) -> Option<Address>,
+) -> Result<()> {
+    let mut has_name = false;
+    let mut has_dropped_reference = false;
+    for attr in entry.attrs.iter() {
+        if !is_kept_attribute(attr.name()) {
+            continue;
+        }
+        match attr.value() {
+            gimli::AttributeValue::UnitRef(offset) if kept.contains(&offset) => {}
+            gimli::AttributeValue::UnitRef(_) | gimli::AttributeValue::DebugInfoRef(_) => {
+                has_dropped_reference = true;
+                continue;
+            }
+            _ => {}
+        }
+        if attr.name() == gimli::DW_AT_name || attr.name() == gimli::DW_AT_linkage_name {
+            has_name = true;
+        }
+        let value = unit.convert_attribute_value(entry.read_unit, attr, convert_address)?;
+        unit.unit.get_mut(id).set(attr.name(), value);
+    }
+    if has_dropped_reference && !has_name {
+        // The name is taken from the dropped declaration, e.g. a method
+        // declared in the structure type.
+        let read_unit = entry.read_unit;
+        if let Some(name) = die_name(read_unit.dwarf, units, read_unit.unit, entry) {
+            unit.unit.get_mut(id).set(
+                gimli::DW_AT_name,
+                write::AttributeValue::String(name.into_bytes()),
+            );
+        }
+    }
+    Ok(())
+}
+
+// Returns the rewritten DWARF sections by name. The relocatable objects are
+// not supported: the relocations refer to the original sections.
+pub fn line_tables_only(debug_sections: &DebugSections) -> Result<HashMap<Vec<u8>, Vec<u8>>> {
+    if debug_sections.linking.is_some() {
+        return Err(Error::DwarfWrite(String::from(
+            "the line tables of relocatable modules cannot be rewritten",
+        )));
+    }
+    let dwarf = gimli::Dwarf::load(|id| -> Result<_> { Ok(load_section(debug_sections, id)) })?;
+    let units = UnitIndex::new(&dwarf);
+    let convert_address = |address| Some(Address::Constant(address));
+    let mut write_dwarf = write::Dwarf::new();
+    {
+        let mut convert = write_dwarf.convert(&dwarf)?;
+        while let Some((mut unit, root_entry)) = convert.read_unit()? {
+            let kept = kept_entries(unit.read_unit)?;
+            if let Some(convert_program) = unit.read_line_program(None, None)? {
+                let (program, files) = convert_program.convert(&convert_address)?;
+                unit.set_line_program(program, files);
+            }
+            let root = unit.unit.root();
+            convert_attributes(
+                &units,
+                &mut unit,
+                root,
+                &root_entry,
+                &kept,
+                &convert_address,
+            )?;
+            let mut entry = root_entry;
+            while let Some(id) = unit.read_entry(&mut entry)? {
+                if !kept.contains(&entry.offset) {
+                    continue;
+                }
+                let id = unit.add_entry(id, &entry);
+                convert_attributes(&units, &mut unit, id, &entry, &kept, &convert_address)?;
+            }
+        }
+    }
+
+    let mut sections = Sections::new(EndianVec::new(LittleEndian));
+    write_dwarf.write(&mut sections)?;
+    let mut tables = HashMap::new();
+    sections.for_each(|id, data| -> Result<()> {
+        if !data.slice().is_empty() {
+            tables.insert(id.name().as_bytes().to_vec(), data.slice().to_vec());
+        }
+        Ok(())
+    })?;
+    Ok(tables)
+}
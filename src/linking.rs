@@ -0,0 +1,418 @@
+// Reads the "linking" section of the relocatable wasm objects.
+
+use wasmparser::BinaryReader;
+
+use error::{Error, Result};
+use wasm_read::{read_var_u64, write_string, write_var_u32, DebugSections, Import};
+
+const WASM_SEGMENT_INFO: u32 = 5;
+const WASM_INIT_FUNCS: u32 = 6;
+const WASM_COMDAT_INFO: u32 = 7;
+const WASM_SYMBOL_TABLE: u32 = 8;
+
+pub const WASM_SYMBOL_BINDING_WEAK: u32 = 0x1;
+pub const WASM_SYMBOL_BINDING_LOCAL: u32 = 0x2;
+pub const WASM_SYMBOL_VISIBILITY_HIDDEN: u32 = 0x4;
+pub const WASM_SYMBOL_UNDEFINED: u32 = 0x10;
+pub const WASM_SYMBOL_EXPORTED: u32 = 0x20;
+pub const WASM_SYMBOL_EXPLICIT_NAME: u32 = 0x40;
+pub const WASM_SYMBOL_NO_STRIP: u32 = 0x80;
+pub const WASM_SYMBOL_TLS: u32 = 0x100;
+pub const WASM_SYMBOL_ABSOLUTE: u32 = 0x200;
+
+const SYMBOL_FLAG_NAMES: [(u32, &str); 9] = [
+    (WASM_SYMBOL_BINDING_WEAK, "weak"),
+    (WASM_SYMBOL_BINDING_LOCAL, "local"),
+    (WASM_SYMBOL_VISIBILITY_HIDDEN, "hidden"),
+    (WASM_SYMBOL_UNDEFINED, "undefined"),
+    (WASM_SYMBOL_EXPORTED, "exported"),
+    (WASM_SYMBOL_EXPLICIT_NAME, "explicit_name"),
+    (WASM_SYMBOL_NO_STRIP, "no_strip"),
+    (WASM_SYMBOL_TLS, "tls"),
+    (WASM_SYMBOL_ABSOLUTE, "absolute"),
+];
+
+// Location of the defined data symbol.
+pub struct DataRef {
+    pub segment: u32,
+    pub offset: u64,
+    pub size: u64,
+}
+
+pub enum SymbolKind {
+    Function(u32),
+    // The undefined data symbols have no location.
+    Data(Option<DataRef>),
+    Global(u32),
+    Section(u32),
+    Tag(u32),
+    Table(u32),
+}
+
+impl SymbolKind {
+    pub fn description(&self) -> &'static str {
+        match *self {
+            SymbolKind::Function(_) => "function",
+            SymbolKind::Data(_) => "data",
+            SymbolKind::Global(_) => "global",
+            SymbolKind::Section(_) => "section",
+            SymbolKind::Tag(_) => "tag",
+            SymbolKind::Table(_) => "table",
+        }
+    }
+
+    // The external kind of the imports that define the undefined symbols.
+    fn import_kind(&self) -> Option<u8> {
+        match *self {
+            SymbolKind::Function(_) => Some(0),
+            SymbolKind::Table(_) => Some(1),
+            SymbolKind::Global(_) => Some(3),
+            SymbolKind::Tag(_) => Some(4),
+            SymbolKind::Data(_) | SymbolKind::Section(_) => None,
+        }
+    }
+}
+
+pub struct Symbol {
+    pub kind: SymbolKind,
+    pub flags: u32,
+    // The undefined symbols without explicit name use the import name, the
+    // section symbols have no name.
+    pub name: Option<String>,
+}
+
+impl Symbol {
+    pub fn is_undefined(&self) -> bool {
+        self.flags & WASM_SYMBOL_UNDEFINED != 0
+    }
+
+    pub fn is_local(&self) -> bool {
+        self.flags & WASM_SYMBOL_BINDING_LOCAL != 0
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.flags & WASM_SYMBOL_BINDING_WEAK != 0
+    }
+
+    pub fn flag_names(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        for &(flag, name) in SYMBOL_FLAG_NAMES.iter() {
+            if self.flags & flag != 0 {
+                names.push(name);
+            }
+        }
+        names
+    }
+}
+
+pub struct SegmentInfo {
+    pub name: String,
+    // Alignment as a power of 2.
+    pub alignment: u32,
+    pub flags: u32,
+}
+
+pub struct InitFunc {
+    pub priority: u32,
+    pub symbol_index: u32,
+}
+
+pub struct ComdatEntry {
+    // 0 - data segment, 1 - function, 2 - global, 3 - tag, 4 - table,
+    // 5 - section.
+    pub kind: u8,
+    pub index: u32,
+}
+
+pub struct Comdat {
+    pub name: String,
+    pub flags: u32,
+    pub entries: Vec<ComdatEntry>,
+}
+
+#[derive(Default)]
+pub struct LinkingInfo {
+    pub version: u32,
+    pub symbols: Vec<Symbol>,
+    pub segments: Vec<SegmentInfo>,
+    pub init_funcs: Vec<InitFunc>,
+    pub comdats: Vec<Comdat>,
+}
+
+fn read_name(reader: &mut BinaryReader) -> Result<String> {
+    Ok(String::from_utf8_lossy(reader.read_string()?).into_owned())
+}
+
+fn read_symbol(reader: &mut BinaryReader, imports: &[Import]) -> Result<Symbol> {
+    let kind = reader.read_u8()?;
+    let flags = reader.read_var_u32()?;
+    let is_undefined = flags & WASM_SYMBOL_UNDEFINED != 0;
+    let has_name = !is_undefined || flags & WASM_SYMBOL_EXPLICIT_NAME != 0;
+    let (kind, name) = match kind {
+        0 | 2 | 4 | 5 => {
+            let index = reader.read_var_u32()?;
+            let name = if has_name {
+                Some(read_name(reader)?)
+            } else {
+                None
+            };
+            let kind = match kind {
+                0 => SymbolKind::Function(index),
+                2 => SymbolKind::Global(index),
+                4 => SymbolKind::Tag(index),
+                _ => SymbolKind::Table(index),
+            };
+            (kind, name)
+        }
+        1 => {
+            let name = read_name(reader)?;
+            let data_ref = if is_undefined {
+                None
+            } else {
+                Some(DataRef {
+                    segment: reader.read_var_u32()?,
+                    offset: read_var_u64(reader)?,
+                    size: read_var_u64(reader)?,
+                })
+            };
+            (SymbolKind::Data(data_ref), Some(name))
+        }
+        3 => (SymbolKind::Section(reader.read_var_u32()?), None),
+        _ => return Err(Error::Relocation(format!("unknown symbol kind {}", kind))),
+    };
+    let name = match (name, is_undefined) {
+        (None, true) => import_name(&kind, imports),
+        (name, _) => name,
+    };
+    Ok(Symbol { kind, flags, name })
+}
+
+// Finds the name of the import that defines the undefined symbol.
+fn import_name(kind: &SymbolKind, imports: &[Import]) -> Option<String> {
+    let index = match *kind {
+        SymbolKind::Function(index)
+        | SymbolKind::Global(index)
+        | SymbolKind::Tag(index)
+        | SymbolKind::Table(index) => index,
+        _ => return None,
+    };
+    let import_kind = kind.import_kind()?;
+    imports
+        .iter()
+        .filter(|import| import.kind == import_kind)
+        .nth(index as usize)
+        .map(|import| import.field.clone())
+}
+
+fn read_comdat(reader: &mut BinaryReader) -> Result<Comdat> {
+    let name = read_name(reader)?;
+    let flags = reader.read_var_u32()?;
+    let count = reader.read_var_u32()?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        entries.push(ComdatEntry {
+            kind: reader.read_u8()? as u8,
+            index: reader.read_var_u32()?,
+        });
+    }
+    Ok(Comdat {
+        name,
+        flags,
+        entries,
+    })
+}
+
+pub fn read_linking(debug_sections: &DebugSections) -> Result<LinkingInfo> {
+    let linking_table = match debug_sections.linking {
+        Some(ref linking_table) => linking_table,
+        None => {
+            return Err(Error::Relocation(String::from(
+                "no linking section (not a relocatable object)",
+            )))
+        }
+    };
+    let mut reader = BinaryReader::new(linking_table);
+    let mut info = LinkingInfo {
+        version: reader.read_var_u32()?,
+        ..LinkingInfo::default()
+    };
+    if info.version != 1 && info.version != 2 {
+        return Err(Error::Relocation(format!(
+            "unsupported linking section version {}",
+            info.version
+        )));
+    }
+    while !reader.eof() {
+        let table_code = reader.read_u8()?;
+        let table = reader.read_string()?;
+        let mut table_reader = BinaryReader::new(table);
+        match table_code {
+            WASM_SEGMENT_INFO => {
+                let count = table_reader.read_var_u32()?;
+                for _ in 0..count {
+                    let segment = SegmentInfo {
+                        name: read_name(&mut table_reader)?,
+                        alignment: table_reader.read_var_u32()?,
+                        flags: table_reader.read_var_u32()?,
+                    };
+                    // The alignment is printed in bytes.
+                    if segment.alignment >= 64 {
+                        return Err(Error::Relocation(format!(
+                            "segment {} has invalid alignment 2^{}",
+                            segment.name, segment.alignment
+                        )));
+                    }
+                    info.segments.push(segment);
+                }
+            }
+            WASM_INIT_FUNCS => {
+                let count = table_reader.read_var_u32()?;
+                for _ in 0..count {
+                    info.init_funcs.push(InitFunc {
+                        priority: table_reader.read_var_u32()?,
+                        symbol_index: table_reader.read_var_u32()?,
+                    });
+                }
+            }
+            WASM_COMDAT_INFO => {
+                let count = table_reader.read_var_u32()?;
+                for _ in 0..count {
+                    info.comdats.push(read_comdat(&mut table_reader)?);
+                }
+            }
+            WASM_SYMBOL_TABLE => {
+                let count = table_reader.read_var_u32()?;
+                for _ in 0..count {
+                    info.symbols
+                        .push(read_symbol(&mut table_reader, &debug_sections.imports)?);
+                }
+            }
+            _ => {
+                // Unknown subsections are skipped.
+            }
+        }
+    }
+    Ok(info)
+}
+
+// Updates the linking section after the sections removal, the section map
+// has the new indices of the sections. The section symbols and comdat
+// entries of the removed sections are dropped. Returns the new linking
+// section and the new indices of the symbols.
+pub(crate) fn remap_linking_sections(
+    linking_table: &[u8],
+    section_map: &[Option<u32>],
+) -> Result<(Vec<u8>, Vec<Option<u32>>)> {
+    let mut reader = BinaryReader::new(linking_table);
+    let version = reader.read_var_u32()?;
+    let mut subsections = Vec::new();
+    while !reader.eof() {
+        let table_code = reader.read_u8()?;
+        subsections.push((table_code, reader.read_string()?));
+    }
+
+    // The symbol table is remapped first, the other subsections refer to
+    // the symbols.
+    let mut symbol_map = Vec::new();
+    let mut symbol_table = None;
+    for &(table_code, table) in subsections.iter() {
+        if table_code != WASM_SYMBOL_TABLE {
+            continue;
+        }
+        let mut table_reader = BinaryReader::new(table);
+        let count = table_reader.read_var_u32()?;
+        let mut symbols = Vec::new();
+        let mut new_count = 0;
+        for _ in 0..count {
+            let start = table_reader.current_position();
+            let symbol = read_symbol(&mut table_reader, &[])?;
+            let new_index = match symbol.kind {
+                SymbolKind::Section(section_index) => {
+                    match section_map.get(section_index as usize).cloned().flatten() {
+                        Some(new_section_index) => {
+                            symbols.push(3);
+                            write_var_u32(&mut symbols, symbol.flags as usize);
+                            write_var_u32(&mut symbols, new_section_index as usize);
+                            Some(new_count)
+                        }
+                        None => None,
+                    }
+                }
+                _ => {
+                    symbols.extend_from_slice(&table[start..table_reader.current_position()]);
+                    Some(new_count)
+                }
+            };
+            if new_index.is_some() {
+                new_count += 1;
+            }
+            symbol_map.push(new_index);
+        }
+        let mut data = Vec::new();
+        write_var_u32(&mut data, new_count as usize);
+        data.extend_from_slice(&symbols);
+        symbol_table = Some(data);
+    }
+
+    let mut result = Vec::new();
+    write_var_u32(&mut result, version as usize);
+    for &(table_code, table) in subsections.iter() {
+        let mut table_reader = BinaryReader::new(table);
+        let data = match table_code {
+            WASM_SYMBOL_TABLE => symbol_table.take().unwrap_or_default(),
+            WASM_INIT_FUNCS => {
+                let count = table_reader.read_var_u32()?;
+                let mut data = Vec::new();
+                write_var_u32(&mut data, count as usize);
+                for _ in 0..count {
+                    let priority = table_reader.read_var_u32()?;
+                    let symbol_index = table_reader.read_var_u32()?;
+                    let symbol_index = match symbol_map.get(symbol_index as usize) {
+                        Some(&Some(symbol_index)) => symbol_index,
+                        _ => {
+                            return Err(Error::Relocation(format!(
+                                "init function symbol {} was removed",
+                                symbol_index
+                            )))
+                        }
+                    };
+                    write_var_u32(&mut data, priority as usize);
+                    write_var_u32(&mut data, symbol_index as usize);
+                }
+                data
+            }
+            WASM_COMDAT_INFO => {
+                let count = table_reader.read_var_u32()?;
+                let mut data = Vec::new();
+                write_var_u32(&mut data, count as usize);
+                for _ in 0..count {
+                    let comdat = read_comdat(&mut table_reader)?;
+                    let mut entries = Vec::new();
+                    for entry in comdat.entries.iter() {
+                        let index = match entry.kind {
+                            // The comdat section entries refer to the
+                            // sections by the index.
+                            5 => section_map.get(entry.index as usize).cloned().flatten(),
+                            _ => Some(entry.index),
+                        };
+                        if let Some(index) = index {
+                            entries.push((entry.kind, index));
+                        }
+                    }
+                    write_string(&mut data, comdat.name.as_bytes());
+                    write_var_u32(&mut data, comdat.flags as usize);
+                    write_var_u32(&mut data, entries.len());
+                    for (kind, index) in entries {
+                        data.push(kind);
+                        write_var_u32(&mut data, index as usize);
+                    }
+                }
+                data
+            }
+            _ => table.to_vec(),
+        };
+        result.push(table_code as u8);
+        write_string(&mut result, &data);
+    }
+    Ok((result, symbol_map))
+}
@@ -0,0 +1,239 @@
+// Resolves wasm code addresses to the source locations.
+
+use dwarf::{DebugLoc, DebugLocInfo};
+
+// Source location of a single (possibly inlined) function frame.
+pub struct Frame {
+    pub name_id: Option<u32>,
+    pub source_id: Option<u32>,
+    pub line: u32,
+    pub column: u32,
+}
+
+// Index of the locations sorted by address, when several locations have
+// the same address, the last one is used unless it ends a sequence.
+pub struct AddressIndex<'a> {
+    di: &'a DebugLocInfo,
+    entries: Vec<(u64, usize)>,
+}
+
+impl<'a> AddressIndex<'a> {
+    pub fn new(di: &'a DebugLocInfo) -> AddressIndex<'a> {
+        let mut sorted: Vec<(u64, usize)> = di
+            .locations
+            .iter()
+            .enumerate()
+            .map(|(index, loc)| (loc.address, index))
+            .collect();
+        sorted.sort_by_key(|&(address, _)| address);
+        let mut entries: Vec<(u64, usize)> = Vec::with_capacity(sorted.len());
+        for (address, index) in sorted {
+            match entries.last_mut() {
+                Some(last) if last.0 == address => {
+                    if !di.locations[index].end_sequence || di.locations[last.1].end_sequence {
+                        last.1 = index;
+                    }
+                }
+                _ => entries.push((address, index)),
+            }
+        }
+        AddressIndex { di, entries }
+    }
+
+    pub fn find_location(&self, address: u64) -> Option<&'a DebugLoc> {
+        let index = self
+            .entries
+            .partition_point(|&(entry_address, _)| entry_address <= address);
+        if index == 0 {
+            return None;
+        }
+        let loc = &self.di.locations[self.entries[index - 1].1];
+        if loc.end_sequence {
+            // The address is located between the sequences.
+            return None;
+        }
+        Some(loc)
+    }
+
+    // Returns the frames for the address, starting from the innermost
+    // inlined function and ending with the function that contains the code.
+    pub fn frames(&self, address: u64) -> Vec<Frame> {
+        let loc = match self.find_location(address) {
+            Some(loc) => loc,
+            None => return Vec::new(),
+        };
+        let function_name_id = self
+            .di
+            .function_at(address)
+            .and_then(|function| function.name_id);
+        let mut frames = Vec::new();
+        let mut source_id = Some(loc.source_id);
+        let mut line = loc.line;
+        let mut column = loc.column;
+        for call in self.di.inline_frames(address).iter().rev() {
+            frames.push(Frame {
+                name_id: call.name_id,
+                source_id,
+                line,
+                column,
+            });
+            source_id = call.call_source_id;
+            line = call.call_line;
+            column = call.call_column;
+        }
+        frames.push(Frame {
+            name_id: function_name_id,
+            source_id,
+            line,
+            column,
+        });
+        frames
+    }
+}
+
+// Code address ranges generated for the source line. The line can differ
+// from the requested one if the latter has no code.
+pub struct LineAddresses {
+    pub line: u32,
+    pub ranges: Vec<(u64, u64)>,
+}
+
+fn is_same_file(source: &str, file: &str) -> bool {
+    if source == file {
+        return true;
+    }
+    source.ends_with(file) && {
+        let prefix = &source[..source.len() - file.len()];
+        prefix.ends_with('/') || prefix.ends_with('\\')
+    }
+}
+
+// Finds the code address ranges for the source file and line. The file is
+// matched against the full source path or its trailing path components.
+// Rows that are marked as the statement beginning are preferred, and when
+// the line has no code, the nearest following line is used.
+pub fn find_line_addresses(di: &DebugLocInfo, file: &str, line: u32) -> Option<LineAddresses> {
+    let source_ids: Vec<u32> = di
+        .sources
+        .iter()
+        .enumerate()
+        .filter(|&(_, source)| is_same_file(source, file))
+        .map(|(id, _)| id as u32)
+        .collect();
+    // Each row covers the addresses until the next row, the locations are
+    // sorted by address.
+    let mut rows: Vec<(&DebugLoc, u64)> = Vec::new();
+    for (i, loc) in di.locations.iter().enumerate() {
+        if loc.end_sequence || loc.line < line || !source_ids.contains(&loc.source_id) {
+            continue;
+        }
+        let end = match di.locations.get(i + 1) {
+            Some(next) => next.address,
+            None => continue,
+        };
+        if end > loc.address {
+            rows.push((loc, end));
+        }
+    }
+    let found_line = rows.iter().map(|&(loc, _)| loc.line).min()?;
+    rows.retain(|&(loc, _)| loc.line == found_line);
+    if rows.iter().any(|&(loc, _)| loc.is_stmt) {
+        rows.retain(|&(loc, _)| loc.is_stmt);
+    }
+
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for (loc, end) in rows {
+        match ranges.last_mut() {
+            Some(last) if last.1 == loc.address => last.1 = end,
+            _ => ranges.push((loc.address, end)),
+        }
+    }
+    Some(LineAddresses {
+        line: found_line,
+        ranges,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loc(address: u64, line: u32, is_stmt: bool) -> DebugLoc {
+        DebugLoc {
+            address,
+            source_id: 0,
+            line,
+            column: 1,
+            name_id: None,
+            is_stmt,
+            basic_block: false,
+            prologue_end: false,
+            epilogue_begin: false,
+            end_sequence: false,
+            isa: 0,
+            discriminator: 0,
+        }
+    }
+
+    fn end_sequence(address: u64) -> DebugLoc {
+        DebugLoc {
+            end_sequence: true,
+            ..loc(address, 0, false)
+        }
+    }
+
+    fn debug_loc_info(locations: Vec<DebugLoc>) -> DebugLocInfo {
+        let mut di = DebugLocInfo::default();
+        di.sources = vec!["src/a.c".to_string(), "src/b.c".to_string()];
+        di.locations = locations;
+        di
+    }
+
+    #[test]
+    fn nearest_following_line() {
+        let di = debug_loc_info(vec![
+            loc(0x10, 3, true),
+            loc(0x14, 7, true),
+            loc(0x18, 5, true),
+            end_sequence(0x20),
+        ]);
+        let result = find_line_addresses(&di, "a.c", 4).unwrap();
+        assert_eq!(result.line, 5);
+        assert_eq!(result.ranges, vec![(0x18, 0x20)]);
+        assert!(find_line_addresses(&di, "a.c", 8).is_none());
+        assert!(find_line_addresses(&di, "b.c", 3).is_none());
+        assert!(find_line_addresses(&di, "/a.c", 3).is_none());
+    }
+
+    #[test]
+    fn prefers_is_stmt() {
+        let di = debug_loc_info(vec![
+            loc(0x10, 3, false),
+            loc(0x14, 4, true),
+            loc(0x18, 3, true),
+            loc(0x1c, 4, false),
+            end_sequence(0x20),
+        ]);
+        let result = find_line_addresses(&di, "src/a.c", 3).unwrap();
+        assert_eq!(result.ranges, vec![(0x18, 0x1c)]);
+        let di = debug_loc_info(vec![loc(0x10, 3, false), end_sequence(0x20)]);
+        let result = find_line_addresses(&di, "src/a.c", 3).unwrap();
+        assert_eq!(result.ranges, vec![(0x10, 0x20)]);
+    }
+
+    #[test]
+    fn merges_adjacent_ranges() {
+        let di = debug_loc_info(vec![
+            loc(0x10, 3, true),
+            loc(0x14, 3, true),
+            loc(0x18, 4, true),
+            loc(0x1c, 3, true),
+            end_sequence(0x20),
+            loc(0x20, 3, true),
+            end_sequence(0x28),
+        ]);
+        let result = find_line_addresses(&di, "a.c", 3).unwrap();
+        assert_eq!(result.line, 3);
+        assert_eq!(result.ranges, vec![(0x10, 0x18), (0x1c, 0x28)]);
+    }
+}
@@ -1,72 +1,57 @@
 use std::env;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
+use std::path::Path;
+use std::process;
 
-use dwarf::get_debug_loc;
 use getopts::Options;
-use reloc::reloc;
-use to_json::convert_debug_info_to_json;
-use wasm_read::{add_source_mapping_url_section, remove_debug_sections, DebugSections};
+use wasm_dwarf::{
+    convert_relocations_to_json, find_line_addresses, is_archive, read_linking, reloc,
+    AddressIndex, ColumnZero, Converter, DebugLocInfo, DebugSections, Error, RelocEntry, Result,
+    SectionPosition, StripProfile, SymbolKind,
+};
 
 extern crate getopts;
-extern crate gimli;
-extern crate rustc_serialize;
-extern crate vlq;
-extern crate wasmparser;
-
-mod dwarf;
-mod reloc;
-mod to_json;
-mod wasm_read;
-
-struct PrefixReplacements {
-    replacements: Vec<(String, String)>,
-}
-
-impl PrefixReplacements {
-    fn parse(input: &Vec<String>) -> PrefixReplacements {
-        let mut replacements = Vec::new();
-        for i in input.iter() {
-            let separator = i.find('=');
-            if let Some(separator_index) = separator {
-                replacements.push((
-                    i.chars().take(separator_index).collect(),
-                    i.chars()
-                        .skip(separator_index + 1)
-                        .take(i.len() - 1 - separator_index)
-                        .collect(),
-                ));
-            } else {
-                replacements.push((i.clone(), String::new()))
-            }
-        }
-        return PrefixReplacements { replacements };
-    }
-
-    fn replace(&self, path: &String) -> String {
-        let mut result = path.clone();
-        for (ref old_prefix, ref new_prefix) in self.replacements.iter() {
-            if path.starts_with(old_prefix) {
-                result = result.split_off(old_prefix.len());
-                result.insert_str(0, new_prefix);
-                return result;
-            }
-        }
-        result
-    }
-
-    fn replace_all(&self, paths: &mut Vec<String>) {
-        for path in paths.iter_mut() {
-            *path = self.replace(&path);
-        }
-    }
-}
+extern crate wasm_dwarf;
 
 fn main() {
     let mut opts = Options::new();
     opts.optopt("o", "", "set output file name", "NAME");
     opts.optflag("", "relocation", "perform relocation first");
     opts.optflag("d", "dump", "print source files and location entries");
+    opts.optflag(
+        "l",
+        "lookup",
+        "print source locations for the addresses listed after the input file or read from stdin",
+    );
+    opts.optflag(
+        "",
+        "symbols",
+        "print the linking section symbols, segments and comdats of the object file",
+    );
+    opts.optflag(
+        "",
+        "print-urls",
+        "print the sourceMappingURL and external_debug_info sections of the module",
+    );
+    opts.optflagopt(
+        "",
+        "relocs",
+        "print the linking section and the relocations applied to the debug sections",
+        "text|json",
+    );
+    opts.optmulti(
+        "r",
+        "reverse-lookup",
+        "print code address ranges for the source line",
+        "FILE:LINE",
+    );
+    opts.optflag(
+        "",
+        "code-offsets",
+        "lookup addresses are relative to the code section instead of the module",
+    );
     opts.optmulti(
         "p",
         "prefix",
@@ -74,107 +59,645 @@ fn main() {
         "OLD_PREFIX[=NEW_PREFIX]",
     );
     opts.optflag("s", "sources", "read and embed source files");
+    opts.optflag(
+        "",
+        "stmt-only",
+        "emit only statement beginning rows to the source map",
+    );
+    opts.optopt(
+        "",
+        "column-zero",
+        "map rows without column to the line beginning (default) or leave them unmapped",
+        "line|unmapped",
+    );
+    opts.optflag(
+        "",
+        "strict",
+        "fail on modules without code or DWARF instead of writing empty source map",
+    );
     opts.optopt("w", "", "set output wasm file", "NAME");
-    opts.optflag("x", "strip", "removes debug and linking sections");
+    opts.optflag("x", "strip", "removes the debug sections");
+    opts.optopt(
+        "",
+        "strip-profile",
+        "removes the debug sections (default, keeps the objects linkable) or all custom \
+         sections except name",
+        "debug|all",
+    );
+    opts.optflag(
+        "",
+        "line-tables-only",
+        "rewrites the DWARF sections to keep only the line tables and the functions names",
+    );
+    opts.optmulti(
+        "",
+        "keep-section",
+        "keeps the custom sections matching the glob pattern when stripping",
+        "PATTERN",
+    );
+    opts.optopt(
+        "",
+        "split-dwarf",
+        "writes the debug sections to the debug file and the stripped module to -w output",
+        "DEBUG_FILE",
+    );
+    opts.optopt(
+        "",
+        "debug-file",
+        "reads the debug sections from the debug file split from the module",
+        "DEBUG_FILE",
+    );
     opts.optopt(
         "m",
         "source-map",
         "specifies sourceMappingURL section contest",
         "URL",
     );
+    opts.optopt(
+        "e",
+        "external-debug-info",
+        "specifies external_debug_info section contents",
+        "URL",
+    );
+    opts.optmulti(
+        "",
+        "remove-section",
+        "removes the custom sections matching the glob pattern from the wasm output",
+        "PATTERN",
+    );
+    opts.optmulti(
+        "",
+        "move-section",
+        "moves the custom sections matching the glob pattern of the wasm output to the end, \
+         or before or after the section, e.g. sourceMappingURL=before:CODE",
+        "PATTERN[=before:NAME|=after:NAME]",
+    );
+    opts.optmulti(
+        "",
+        "producer",
+        "adds the tool to the producers section field, e.g. processed-by=tool@1.0",
+        "FIELD=NAME[@VERSION]",
+    );
+    opts.optopt(
+        "",
+        "module-name",
+        "sets the module name in the name section",
+        "NAME",
+    );
+    opts.optopt("", "build-id", "sets the build_id section contents", "HEX");
     opts.optflag("h", "help", "print this help menu");
 
     let args: Vec<_> = env::args().collect();
     let program = args[0].clone();
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
-        Err(f) => panic!(f.to_string()),
+        Err(f) => usage_error(&f.to_string()),
     };
-    if matches.opt_present("h") || matches.free.len() < 1
-        || !(matches.opt_present("o") || matches.opt_present("d"))
+    if matches.opt_present("h")
+        || matches.free.is_empty()
+        || !(matches.opt_present("o")
+            || matches.opt_present("d")
+            || matches.opt_present("l")
+            || matches.opt_present("r")
+            || matches.opt_present("symbols")
+            || matches.opt_present("print-urls")
+            || matches.opt_present("split-dwarf")
+            || matches.opt_present("relocs"))
     {
         return print_usage(&program, opts);
     }
 
-    let perform_reloc = matches.opt_present("relocation");
-    let filename = matches.free[0].clone();
-    let mut f = File::open(filename).expect("file not found");
+    let column_zero = match matches.opt_str("column-zero").as_deref() {
+        None | Some("line") => ColumnZero::LineOnly,
+        Some("unmapped") => ColumnZero::Unmapped,
+        Some(value) => usage_error(&format!("unexpected --column-zero value: {}", value)),
+    };
+    let mut converter = Converter::new()
+        .relocation(matches.opt_present("relocation"))
+        .embed_sources(matches.opt_present("sources"))
+        .is_stmt_only(matches.opt_present("stmt-only"))
+        .column_zero(column_zero)
+        .strict(matches.opt_present("strict"));
+    if matches.opt_present("x") || matches.opt_present("strip-profile") {
+        converter = match matches.opt_str("strip-profile").as_deref() {
+            None | Some("debug") => converter.strip_profile(StripProfile::DebugOnly),
+            Some("all") => converter.strip_profile(StripProfile::AllCustom),
+            Some(value) => usage_error(&format!("unexpected --strip-profile value: {}", value)),
+        };
+    }
+    if matches.opt_present("line-tables-only") {
+        converter = converter.line_tables_only(true);
+    }
+    for pattern in matches.opt_strs("keep-section") {
+        converter = converter.keep_sections(&pattern);
+    }
+    for prefix in matches.opt_strs("prefix") {
+        converter = match prefix.find('=') {
+            Some(separator_index) => {
+                converter.prefix(&prefix[..separator_index], &prefix[separator_index + 1..])
+            }
+            None => converter.prefix(&prefix, ""),
+        };
+    }
+    if let Some(url) = matches.opt_str("m") {
+        converter = converter.source_mapping_url(&url);
+    }
+    if let Some(url) = matches.opt_str("e") {
+        converter = converter.external_debug_info(&url);
+    }
+    for pattern in matches.opt_strs("remove-section") {
+        converter = converter.remove_sections(&pattern);
+    }
+    for moved in matches.opt_strs("move-section") {
+        let (pattern, position) = match moved.find('=') {
+            Some(separator_index) => {
+                let position = &moved[separator_index + 1..];
+                let position = if let Some(name) = position.strip_prefix("before:") {
+                    SectionPosition::Before(String::from(name))
+                } else if let Some(name) = position.strip_prefix("after:") {
+                    SectionPosition::After(String::from(name))
+                } else {
+                    usage_error(&format!("unexpected --move-section value: {}", moved))
+                };
+                (&moved[..separator_index], position)
+            }
+            None => (moved.as_str(), SectionPosition::End),
+        };
+        converter = converter.move_sections(pattern, position);
+    }
+    for producer in matches.opt_strs("producer") {
+        let (field, value) = match producer.find('=') {
+            Some(separator_index) => (
+                &producer[..separator_index],
+                &producer[separator_index + 1..],
+            ),
+            None => usage_error(&format!("unexpected --producer value: {}", producer)),
+        };
+        converter = match value.find('@') {
+            Some(separator_index) => converter.producer(
+                field,
+                &value[..separator_index],
+                &value[separator_index + 1..],
+            ),
+            None => converter.producer(field, value, ""),
+        };
+    }
+    if let Some(name) = matches.opt_str("module-name") {
+        converter = converter.module_name(&name);
+    }
+    if let Some(build_id) = matches.opt_str("build-id") {
+        converter = converter.build_id(&parse_hex(&build_id));
+    }
+
+    if let Err(err) = run(&matches, &converter) {
+        eprintln!("error: {}", err);
+        process::exit(err.exit_code());
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(2);
+}
+
+fn parse_hex(value: &str) -> Vec<u8> {
+    let digits = value.as_bytes();
+    if digits.len() & 1 != 0 || !digits.iter().all(u8::is_ascii_hexdigit) {
+        usage_error(&format!("unexpected hex value: {}", value));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+        .collect()
+}
+
+fn file_error(filename: &str) -> impl Fn(io::Error) -> Error + '_ {
+    move |error| Error::FileIo {
+        path: filename.to_owned(),
+        error,
+    }
+}
+
+fn read_file(filename: &str) -> Result<Vec<u8>> {
     let mut data = Vec::new();
-    f.read_to_end(&mut data).expect("unable to read file");
+    File::open(filename)
+        .and_then(|mut f| f.read_to_end(&mut data))
+        .map_err(file_error(filename))?;
+    Ok(data)
+}
 
-    let mut debug_sections = DebugSections::read_sections(data.as_slice());
+fn write_file(filename: &str, data: &[u8]) -> Result<()> {
+    File::create(filename)
+        .and_then(|mut f| f.write_all(data))
+        .map_err(file_error(filename))
+}
 
-    if perform_reloc {
-        if debug_sections.linking.is_none() {
-            panic!("relocation information was not found");
-        }
-        reloc(&mut debug_sections);
+fn run(matches: &getopts::Matches, converter: &Converter) -> Result<()> {
+    // The lookup addresses follow the input file.
+    let filenames = if matches.opt_present("lookup") {
+        &matches.free[..1]
+    } else {
+        &matches.free[..]
+    };
+    let mut files = Vec::new();
+    for filename in filenames {
+        files.push(read_file(filename)?);
     }
+    let data = &files[0];
 
-    let as_json = matches.opt_present("o");
-    let mut di = get_debug_loc(&debug_sections);
+    // Several object files or an archive are combined as if they were linked.
+    let combined = files.len() > 1 || is_archive(data);
 
-    if matches.opt_present("sources") {
-        let mut sources = Vec::new();
-        for file in di.sources.iter() {
-            let mut f = File::open(file).expect("file not found");
-            let mut data = Vec::new();
-            f.read_to_end(&mut data).expect("unable to read file");
-            sources.push(String::from_utf8(data).unwrap());
+    if let Some(debug_file) = matches.opt_str("split-dwarf") {
+        if combined {
+            usage_error("--split-dwarf requires a single wasm input");
+        }
+        let wasm_output = match matches.opt_str("w") {
+            Some(wasm_output) => wasm_output,
+            None => usage_error("--split-dwarf requires -w output"),
+        };
+        // The source map and the dumps are produced from the module with the
+        // debug sections, e.g. with --debug-file.
+        for option in [
+            "o",
+            "d",
+            "l",
+            "r",
+            "symbols",
+            "print-urls",
+            "relocs",
+            "line-tables-only",
+            "debug-file",
+        ]
+        .iter()
+        {
+            if matches.opt_present(option) {
+                let name = if option.len() == 1 { "-" } else { "--" };
+                usage_error(&format!(
+                    "--split-dwarf cannot be used with {}{}",
+                    name, option
+                ));
+            }
         }
-        di.sources_content = Some(sources);
+        // The stripped module refers to the debug file by its name, unless
+        // the URL is specified.
+        let debug_url = match matches.opt_str("e") {
+            Some(url) => url,
+            None => match Path::new(&debug_file).file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => debug_file.clone(),
+            },
+        };
+        let (stripped, debug_wasm) = converter.split_debug_info(data, &debug_url)?;
+        write_file(&wasm_output, &stripped)?;
+        write_file(&debug_file, &debug_wasm)?;
+        return Ok(());
     }
-
-    if matches.opt_present("prefix") {
-        let prefix_replacements = PrefixReplacements::parse(&matches.opt_strs("prefix"));
-        prefix_replacements.replace_all(&mut di.sources);
+    if matches.opt_present("print-urls") {
+        if combined {
+            usage_error("--print-urls requires a single wasm input");
+        }
+        let debug_sections = DebugSections::read_sections(data)?;
+        print_warnings(&debug_sections.warnings);
+        if let Some(ref url) = debug_sections.source_mapping_url {
+            println!("sourceMappingURL: {}", url);
+        }
+        if let Some(ref url) = debug_sections.external_debug_info {
+            println!("external_debug_info: {}", url);
+        }
+        if debug_sections.source_mapping_url.is_none()
+            && debug_sections.external_debug_info.is_none()
+        {
+            eprintln!("warning: no sourceMappingURL or external_debug_info section found");
+        }
+        return Ok(());
     }
+    if matches.opt_present("relocs") {
+        if combined {
+            usage_error("--relocs requires a single wasm input");
+        }
+        let mut debug_sections = DebugSections::read_sections(data)?;
+        print_warnings(&debug_sections.warnings);
+        let relocations = reloc(&mut debug_sections)?;
+        match matches.opt_str("relocs").as_deref() {
+            None | Some("text") => {
+                print_symbols(&debug_sections)?;
+                print_relocations(&relocations);
+            }
+            Some("json") => {
+                let linking = read_linking(&debug_sections)?;
+                println!("{}", convert_relocations_to_json(&linking, &relocations));
+            }
+            Some(value) => usage_error(&format!("unexpected --relocs value: {}", value)),
+        }
+        return Ok(());
+    }
+    let (debug_sections, di) = if combined {
+        if matches.opt_present("symbols") || matches.opt_present("w") {
+            usage_error("--symbols and -w require a single wasm input");
+        }
+        let files: Vec<&[u8]> = files.iter().map(|data| &data[..]).collect();
+        let objects = converter.read_objects(&files)?;
+        for debug_sections in objects.iter() {
+            print_warnings(&debug_sections.warnings);
+        }
+        (None, converter.combined_debug_info(&objects)?)
+    } else {
+        let debug_sections = match matches.opt_str("debug-file") {
+            Some(debug_file) => {
+                converter.read_split_debug_sections(data, &read_file(&debug_file)?)?
+            }
+            None => converter.read_debug_sections(data)?,
+        };
+        print_warnings(&debug_sections.warnings);
+        let di = converter.debug_info(&debug_sections)?;
+        (Some(debug_sections), di)
+    };
+    print_warnings(&di.warnings);
 
-    if as_json {
+    if matches.opt_present("lookup") {
+        let mut addresses = matches.free[1..].to_vec();
+        if addresses.is_empty() {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                addresses.extend(line?.split_whitespace().map(String::from));
+            }
+        }
+        let base = match debug_sections {
+            Some(ref debug_sections) if matches.opt_present("code-offsets") => {
+                debug_sections.code_content as u64
+            }
+            _ => 0,
+        };
+        let index = AddressIndex::new(&di);
+        for address_str in addresses.iter() {
+            let address = match u64::from_str_radix(address_str.trim_start_matches("0x"), 16) {
+                Ok(address) => base + address,
+                Err(_) => {
+                    println!("{}: invalid address", address_str);
+                    continue;
+                }
+            };
+            print_lookup(address, &index, &di);
+        }
+    } else if matches.opt_present("reverse-lookup") {
+        for file_line in matches.opt_strs("reverse-lookup") {
+            let parsed = match file_line.rfind(':') {
+                Some(separator_index) => file_line[separator_index + 1..]
+                    .parse::<u32>()
+                    .ok()
+                    .map(|line| (&file_line[..separator_index], line)),
+                None => None,
+            };
+            let (file, line) = match parsed {
+                Some(parsed) => parsed,
+                None => {
+                    println!("{}: invalid source line", file_line);
+                    continue;
+                }
+            };
+            match find_line_addresses(&di, file, line) {
+                Some(result) => {
+                    println!("{}:{}", file, result.line);
+                    for (begin, end) in result.ranges {
+                        println!("  0x{:x}-0x{:x}", begin, end);
+                    }
+                }
+                None => println!("{}: no code found", file_line),
+            }
+        }
+    } else if matches.opt_present("symbols") {
+        let debug_sections = debug_sections.as_ref().unwrap();
+        print_symbols(debug_sections)?;
+    } else if matches.opt_present("o") {
         let output = matches.opt_str("o").unwrap();
-        let result = convert_debug_info_to_json(&di).to_string();
+        let result = converter.source_map(&di);
         if output == "-" {
             println!("{}", result);
         } else {
-            let mut f_out = File::create(output).expect("file cannot be created");
-            f_out.write(result.as_bytes()).expect("data written");
+            write_file(&output, result.as_bytes())?;
         }
     } else {
+        println!(
+            "discarded sequences: {} tombstone, {} outside of ranges",
+            di.discarded_sequences.tombstone, di.discarded_sequences.outside_ranges
+        );
         for (id, path) in di.sources.iter().enumerate() {
             println!("source {}: {}", id, path);
         }
-        for loc in di.locations {
+        for (id, name) in di.names.iter().enumerate() {
+            println!("name {}: {}", id, name);
+        }
+        for loc in di.locations.iter() {
+            let name = match loc.name_id {
+                Some(name_id) => &di.names[name_id as usize],
+                None => "",
+            };
+            let mut flags = Vec::new();
+            if loc.is_stmt {
+                flags.push(String::from("is_stmt"));
+            }
+            if loc.basic_block {
+                flags.push(String::from("basic_block"));
+            }
+            if loc.prologue_end {
+                flags.push(String::from("prologue_end"));
+            }
+            if loc.epilogue_begin {
+                flags.push(String::from("epilogue_begin"));
+            }
+            if loc.end_sequence {
+                flags.push(String::from("end_sequence"));
+            }
+            if loc.isa != 0 {
+                flags.push(format!("isa={}", loc.isa));
+            }
+            if loc.discriminator != 0 {
+                flags.push(format!("discriminator={}", loc.discriminator));
+            }
+            let flags = if flags.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", flags.join(" "))
+            };
             println!(
-                "{:x} @ {},{} ({})",
-                loc.address, loc.line, loc.column, loc.source_id
+                "{:x} @ {},{} ({}) {}{}",
+                loc.address, loc.line, loc.column, loc.source_id, name, flags
             );
+            for call in di.inline_frames(loc.address).iter().rev() {
+                let name = match call.name_id {
+                    Some(name_id) => &di.names[name_id as usize],
+                    None => "<unknown>",
+                };
+                let source = match call.call_source_id {
+                    Some(source_id) => source_id.to_string(),
+                    None => String::from("?"),
+                };
+                println!(
+                    "  inlined {} from {},{} ({})",
+                    name, call.call_line, call.call_column, source
+                );
+            }
         }
     }
 
     if matches.opt_present("w") {
         let wasm_output = matches.opt_str("w").unwrap();
-        let mut modified_wasm = None;
-        if matches.opt_present("x") {
-            modified_wasm = Some(Vec::new());
-            remove_debug_sections(&data, modified_wasm.as_mut().unwrap());
+        let (wasm, report) = converter.rewrite_wasm_with_report(data)?;
+        write_file(&wasm_output, &wasm)?;
+        for section in report.removed.iter() {
+            eprintln!("removed section {} ({} bytes)", section.name, section.size);
+        }
+        if !report.removed.is_empty() {
+            eprintln!("saved {} bytes", report.bytes_saved);
+        }
+    }
+
+    if !di.skipped_units.is_empty() {
+        eprintln!(
+            "warning: skipped {} compilation unit(s):",
+            di.skipped_units.len()
+        );
+        for unit in di.skipped_units.iter() {
+            eprintln!(
+                "  0x{:x} {}: {}",
+                unit.offset,
+                unit.name.as_deref().unwrap_or("<unknown>"),
+                unit.error
+            );
         }
-        if matches.opt_present("m") {
-            if modified_wasm.is_none() {
-                modified_wasm = Some(Vec::new());
-                modified_wasm.as_mut().unwrap().extend_from_slice(&data);
+    }
+    Ok(())
+}
+
+fn print_warnings(warnings: &[Error]) {
+    for warning in warnings.iter() {
+        eprintln!("warning: {}", warning);
+    }
+}
+
+fn print_lookup(address: u64, index: &AddressIndex, di: &DebugLocInfo) {
+    println!("0x{:x}", address);
+    let frames = index.frames(address);
+    if frames.is_empty() {
+        println!("??:0:0");
+        return;
+    }
+    for (i, frame) in frames.iter().enumerate() {
+        let name = match frame.name_id {
+            Some(name_id) => &di.names[name_id as usize],
+            None => "??",
+        };
+        let source = match frame.source_id {
+            Some(source_id) => &di.sources[source_id as usize],
+            None => "??",
+        };
+        println!(
+            "{}{} at {}:{}:{}",
+            if i > 0 { " (inlined by) " } else { "" },
+            name,
+            source,
+            frame.line,
+            frame.column
+        );
+    }
+}
+
+fn print_symbols(debug_sections: &DebugSections) -> Result<()> {
+    let linking = read_linking(debug_sections)?;
+    println!("linking version {}", linking.version);
+    for (index, symbol) in linking.symbols.iter().enumerate() {
+        let name = symbol.name.as_deref().unwrap_or("");
+        let description = match symbol.kind {
+            SymbolKind::Function(index) => format!("function {} {}", index, name),
+            SymbolKind::Data(Some(ref data_ref)) => format!(
+                "data {} segment {} offset 0x{:x} size {}",
+                name, data_ref.segment, data_ref.offset, data_ref.size
+            ),
+            SymbolKind::Data(None) => format!("data {}", name),
+            SymbolKind::Global(index) => format!("global {} {}", index, name),
+            SymbolKind::Section(index) => {
+                let section_name = debug_sections
+                    .tables_index
+                    .get(&(index as usize))
+                    .map(|name| String::from_utf8_lossy(name).into_owned())
+                    .unwrap_or_default();
+                format!("section {} {}", index, section_name)
             }
-            let url = matches.opt_str("m").unwrap();
-            add_source_mapping_url_section(&url, modified_wasm.as_mut().unwrap());
+            SymbolKind::Tag(index) => format!("tag {} {}", index, name),
+            SymbolKind::Table(index) => format!("table {} {}", index, name),
+        };
+        let flags = symbol.flag_names();
+        let flags = if flags.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", flags.join(" "))
+        };
+        println!("symbol {}: {}{}", index, description.trim_end(), flags);
+    }
+    for (index, segment) in linking.segments.iter().enumerate() {
+        println!(
+            "segment {}: {} align {} flags 0x{:x}",
+            index,
+            segment.name,
+            1u64 << segment.alignment,
+            segment.flags
+        );
+    }
+    for init_func in linking.init_funcs.iter() {
+        println!(
+            "init func priority {}: symbol {}",
+            init_func.priority, init_func.symbol_index
+        );
+    }
+    for comdat in linking.comdats.iter() {
+        let entries: Vec<String> = comdat
+            .entries
+            .iter()
+            .map(|entry| {
+                let kind = match entry.kind {
+                    0 => "data",
+                    1 => "function",
+                    2 => "global",
+                    3 => "tag",
+                    4 => "table",
+                    5 => "section",
+                    _ => "unknown",
+                };
+                format!("{} {}", kind, entry.index)
+            })
+            .collect();
+        println!("comdat {}: {}", comdat.name, entries.join(", "));
+    }
+    Ok(())
+}
+
+fn print_relocations(relocations: &[RelocEntry]) {
+    let mut last_reloc_section = None;
+    for relocation in relocations.iter() {
+        if last_reloc_section != Some(&relocation.reloc_section) {
+            println!("{} -> {}", relocation.reloc_section, relocation.section);
+            last_reloc_section = Some(&relocation.reloc_section);
         }
-        let mut f_out = File::create(wasm_output).expect("file cannot be created");
-        f_out
-            .write(&modified_wasm.unwrap_or(data))
-            .expect("wasm data written");
+        println!(
+            "  0x{:x} {} symbol {} {}{:+}: 0x{:x} -> 0x{:x}{}",
+            relocation.offset,
+            relocation.type_name,
+            relocation.symbol_index,
+            relocation.symbol_name.as_deref().unwrap_or(""),
+            relocation.addend,
+            relocation.old_value,
+            relocation.new_value,
+            match relocation.base {
+                Some(ref base) => format!(" relative to {}", base),
+                None => String::new(),
+            }
+        );
     }
 }
 
 fn print_usage(program: &str, opts: Options) {
-    let brief = format!("Usage: {} [options] <INPUT>", program);
+    let brief = format!("Usage: {} [options] <INPUT>...", program);
     print!("{}", opts.usage(&brief));
     println!(
         "
@@ -184,6 +707,34 @@ Usage:
 
     # Read and convert to JSON
     wasm-dwarf foo.wasm -o foo.map
+
+    # Print source locations for the module offsets
+    wasm-dwarf -l foo.wasm 0x7d 0x12e
+
+    # Print code addresses for the source line
+    wasm-dwarf -r foo.c:123 foo.wasm
+
+    # Combine the object files and archives as if they were linked
+    wasm-dwarf foo.o bar.o libbaz.a -o foo.map
+
+    # Print the symbols of the object file
+    wasm-dwarf --symbols foo.o
+
+    # Print the relocations of the debug sections as JSON
+    wasm-dwarf --relocs=json foo.o
+
+Exit codes:
+
+    2  invalid command line arguments
+    3  wasm module cannot be parsed
+    4  DWARF cannot be parsed
+    5  no DWARF debug information found (--strict)
+    6  relocation failed
+    7  I/O error
+    8  source file cannot be read
+    9  no code section found (--strict)
+    10 DWARF cannot be rewritten (--line-tables-only)
+    11 debug file does not match the module (--debug-file)
 "
     );
 }
@@ -1,131 +1,538 @@
 // Applies reloction entries to the existing sections.
-use std::collections::HashMap;
-
 use wasmparser::BinaryReader;
 
-use wasm_read::DebugSections;
+use error::{Error, Result};
+use linking::{read_linking, Symbol, SymbolKind};
+use wasm_read::{write_var_i64, write_var_u32, DebugSections, SegmentOffset};
 
-fn to_vec(b: &[u8]) -> Vec<u8> {
-    let mut result = Vec::new();
-    result.extend_from_slice(b);
-    result
+// How the relocated value is stored at the fixup offset.
+#[derive(Clone, Copy)]
+enum RelocEncoding {
+    // Padded to 5 bytes.
+    Leb,
+    Sleb,
+    // Padded to 10 bytes.
+    Leb64,
+    Sleb64,
+    I32,
+    I64,
 }
 
-enum SymbolKind {
-    Section(u32),
-    Data(u32, u32),
-}
+impl RelocEncoding {
+    fn size(self) -> usize {
+        match self {
+            RelocEncoding::Leb | RelocEncoding::Sleb => 5,
+            RelocEncoding::Leb64 | RelocEncoding::Sleb64 => 10,
+            RelocEncoding::I32 => 4,
+            RelocEncoding::I64 => 8,
+        }
+    }
 
-pub fn reloc(debug_sections: &mut DebugSections) {
-    let (func_indices, symbols) = {
-        let ref linking_table = debug_sections.linking.as_ref().unwrap();
-        let mut reader = BinaryReader::new(&linking_table);
-        let version = reader.read_var_u32().unwrap();
-        assert!(version == 1);
-        let mut symbols: HashMap<u32, SymbolKind> = HashMap::new();
-        let mut func_indices: HashMap<u32, u32> = HashMap::new();
-        while !reader.eof() {
-            let table_code = reader.read_var_u32().unwrap();
-            let table = reader.read_string().unwrap();
-            if table_code == 0x8
-            /* WASM_SYMBOL_TABLE */
-            {
-                let mut table_reader = BinaryReader::new(table);
-                let table_len = table_reader.read_var_u32().unwrap();
-                for index in 0..table_len {
-                    let symbol_kind = table_reader.read_var_u32().unwrap();
-                    let symbol_flags = table_reader.read_var_u32().unwrap();
-                    let wasm_symbol_undefined_flag = 0x10; /* _WASM_SYMBOL_UNDEFINED */
-                    // println!("k{}", symbol_kind);
-                    match symbol_kind {
-                        0x0 /* WASM_SYMBOL_TYPE_FUNCTION */ => {
-                            let elem_index = table_reader.read_var_u32().unwrap();
-                            if (symbol_flags & wasm_symbol_undefined_flag) == 0 {
-                                table_reader.read_string().unwrap();
-                            }
-                            func_indices.insert(index as u32, elem_index);
-                        }
-                        0x2 /* WASM_SYMBOL_TYPE_GLOBAL */ => {
-                            table_reader.read_var_u32().unwrap();
-                            if (symbol_flags & wasm_symbol_undefined_flag) == 0 {
-                                table_reader.read_string().unwrap();
-                            }
-                        }
-                        0x1 /* WASM_SYMBOL_TYPE_DATA */ => {
-                            table_reader.read_string().unwrap();
-                            if (symbol_flags & wasm_symbol_undefined_flag) == 0 {
-                                let data_segment = table_reader.read_var_u32().unwrap();
-                                let offset = table_reader.read_var_u32().unwrap();
-                                table_reader.read_var_u32().unwrap();
-
-                                symbols.insert(index as u32, SymbolKind::Data(data_segment, offset));
-                            }
-                        }
-                        0x3 /* WASM_SYMBOL_TYPE_SECTION */ => {
-                            let section_index = table_reader.read_var_u32().unwrap();
-                            symbols.insert(index as u32, SymbolKind::Section(section_index));
-                        }
-                        _ => panic!("unknown symbol kind")
+    fn read(self, bytes: &[u8]) -> u64 {
+        match self {
+            RelocEncoding::I32 | RelocEncoding::I64 => bytes
+                .iter()
+                .rev()
+                .fold(0u64, |value, &byte| (value << 8) | byte as u64),
+            RelocEncoding::Leb | RelocEncoding::Leb64 => {
+                let mut value = 0u64;
+                for (i, &byte) in bytes.iter().enumerate() {
+                    value |= ((byte & 0x7f) as u64) << (7 * i);
+                    if byte & 0x80 == 0 {
+                        break;
                     }
                 }
+                value
+            }
+            RelocEncoding::Sleb | RelocEncoding::Sleb64 => {
+                let mut value = 0i64;
+                let mut shift = 0;
+                for &byte in bytes.iter() {
+                    value |= ((byte & 0x7f) as i64) << shift;
+                    shift += 7;
+                    if byte & 0x80 == 0 {
+                        break;
+                    }
+                }
+                if shift < 64 {
+                    value = (value << (64 - shift)) >> (64 - shift);
+                }
+                value as u64
             }
         }
-        (func_indices, symbols)
-    };
+    }
+
+    fn write(self, bytes: &mut [u8], value: u64) {
+        let size = bytes.len();
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = match self {
+                RelocEncoding::I32 | RelocEncoding::I64 => (value >> (8 * i)) as u8,
+                RelocEncoding::Leb | RelocEncoding::Leb64 => {
+                    let bits = (value.checked_shr(7 * i as u32).unwrap_or(0) & 0x7f) as u8;
+                    if i + 1 < size {
+                        bits | 0x80
+                    } else {
+                        bits
+                    }
+                }
+                RelocEncoding::Sleb | RelocEncoding::Sleb64 => {
+                    let shift = (7 * i as u32).min(63);
+                    let bits = (((value as i64) >> shift) & 0x7f) as u8;
+                    if i + 1 < size {
+                        bits | 0x80
+                    } else {
+                        bits
+                    }
+                }
+            };
+        }
+    }
+}
+
+// What the relocation entry refers to.
+#[derive(Clone, Copy, PartialEq)]
+enum RelocTarget {
+    FunctionIndex,
+    TableIndex,
+    MemoryAddress,
+    // The memory address relative to the fixup location.
+    MemoryAddressLocRel,
+    TypeIndex,
+    GlobalIndex,
+    FunctionOffset,
+    SectionOffset,
+    TagIndex,
+    TableNumber,
+}
+
+struct RelocType {
+    name: &'static str,
+    target: RelocTarget,
+    encoding: RelocEncoding,
+}
+
+impl RelocType {
+    const fn new(name: &'static str, target: RelocTarget, encoding: RelocEncoding) -> RelocType {
+        RelocType {
+            name,
+            target,
+            encoding,
+        }
+    }
+
+    fn has_addend(&self) -> bool {
+        matches!(
+            self.target,
+            RelocTarget::MemoryAddress
+                | RelocTarget::MemoryAddressLocRel
+                | RelocTarget::FunctionOffset
+                | RelocTarget::SectionOffset
+        )
+    }
+}
+
+// Relocation types as defined by the tool-conventions Linking.md, indexed by
+// the type code.
+const RELOC_TYPES: &[RelocType] = {
+    use self::RelocEncoding::*;
+    use self::RelocTarget::*;
+    &[
+        RelocType::new("R_WASM_FUNCTION_INDEX_LEB", FunctionIndex, Leb),
+        RelocType::new("R_WASM_TABLE_INDEX_SLEB", TableIndex, Sleb),
+        RelocType::new("R_WASM_TABLE_INDEX_I32", TableIndex, I32),
+        RelocType::new("R_WASM_MEMORY_ADDR_LEB", MemoryAddress, Leb),
+        RelocType::new("R_WASM_MEMORY_ADDR_SLEB", MemoryAddress, Sleb),
+        RelocType::new("R_WASM_MEMORY_ADDR_I32", MemoryAddress, I32),
+        RelocType::new("R_WASM_TYPE_INDEX_LEB", TypeIndex, Leb),
+        RelocType::new("R_WASM_GLOBAL_INDEX_LEB", GlobalIndex, Leb),
+        RelocType::new("R_WASM_FUNCTION_OFFSET_I32", FunctionOffset, I32),
+        RelocType::new("R_WASM_SECTION_OFFSET_I32", SectionOffset, I32),
+        RelocType::new("R_WASM_TAG_INDEX_LEB", TagIndex, Leb),
+        RelocType::new("R_WASM_MEMORY_ADDR_REL_SLEB", MemoryAddress, Sleb),
+        RelocType::new("R_WASM_TABLE_INDEX_REL_SLEB", TableIndex, Sleb),
+        RelocType::new("R_WASM_GLOBAL_INDEX_I32", GlobalIndex, I32),
+        RelocType::new("R_WASM_MEMORY_ADDR_LEB64", MemoryAddress, Leb64),
+        RelocType::new("R_WASM_MEMORY_ADDR_SLEB64", MemoryAddress, Sleb64),
+        RelocType::new("R_WASM_MEMORY_ADDR_I64", MemoryAddress, I64),
+        RelocType::new("R_WASM_MEMORY_ADDR_REL_SLEB64", MemoryAddress, Sleb64),
+        RelocType::new("R_WASM_TABLE_INDEX_SLEB64", TableIndex, Sleb64),
+        RelocType::new("R_WASM_TABLE_INDEX_I64", TableIndex, I64),
+        RelocType::new("R_WASM_TABLE_NUMBER_LEB", TableNumber, Leb),
+        RelocType::new("R_WASM_MEMORY_ADDR_TLS_SLEB", MemoryAddress, Sleb),
+        RelocType::new("R_WASM_FUNCTION_OFFSET_I64", FunctionOffset, I64),
+        RelocType::new("R_WASM_MEMORY_ADDR_LOCREL_I32", MemoryAddressLocRel, I32),
+        RelocType::new("R_WASM_TABLE_INDEX_REL_SLEB64", TableIndex, Sleb64),
+        RelocType::new("R_WASM_MEMORY_ADDR_TLS_SLEB64", MemoryAddress, Sleb64),
+        RelocType::new("R_WASM_FUNCTION_INDEX_I32", FunctionIndex, I32),
+    ]
+};
+
+// Relocation entry applied to a debug section.
+pub struct RelocEntry {
+    pub reloc_section: String,
+    pub section: String,
+    pub type_name: &'static str,
+    pub offset: usize,
+    pub symbol_index: u32,
+    // The section symbols are named after the section.
+    pub symbol_name: Option<String>,
+    pub addend: i64,
+    pub old_value: u64,
+    pub new_value: u64,
+    // The new value is relative to the base when the data segment is placed
+    // at runtime, e.g. "__memory_base".
+    pub base: Option<String>,
+}
+
+// Names the global used as the data segment base: the imported globals use
+// the import name, the defined ones the symbol name if any.
+fn global_name(debug_sections: &DebugSections, symbols: &[Symbol], global_index: u32) -> String {
+    let import = debug_sections
+        .imports
+        .iter()
+        .filter(|import| import.kind == 3)
+        .nth(global_index as usize);
+    if let Some(import) = import {
+        return import.field.clone();
+    }
+    symbols
+        .iter()
+        .filter_map(|symbol| match symbol.kind {
+            SymbolKind::Global(index) if index == global_index => symbol.name.clone(),
+            _ => None,
+        })
+        .next()
+        .unwrap_or_else(|| format!("global {}", global_index))
+}
+
+// Applies the relocations to the debug sections and returns the applied
+// entries, ordered by the reloc section name.
+pub fn reloc(debug_sections: &mut DebugSections) -> Result<Vec<RelocEntry>> {
+    let linking = read_linking(debug_sections)?;
 
     let reloc_tables_names = {
         let mut reloc_tables_names = Vec::new();
         for key in debug_sections.reloc_tables.keys() {
             reloc_tables_names.push(key.clone());
         }
+        reloc_tables_names.sort();
         reloc_tables_names
     };
+    let mut entries = Vec::new();
 
-    for ref reloc_table_name in reloc_tables_names {
+    for reloc_table_name in &reloc_tables_names {
         let reloc_table = debug_sections.reloc_tables[reloc_table_name].clone();
-        let fixup_section_name = &reloc_table_name[6..];
         let mut reader = BinaryReader::new(&reloc_table);
-        reader.read_var_u32().unwrap(); // TODO use section_index
-        let count = reader.read_var_u32().unwrap();
+        let section_index = reader.read_var_u32()? as usize;
+        let fixup_section_name = match debug_sections.tables_index.get(&section_index) {
+            Some(name) => name.clone(),
+            None => {
+                return Err(Error::Relocation(format!(
+                    "{} refers to unknown section {}",
+                    String::from_utf8_lossy(reloc_table_name),
+                    section_index
+                )))
+            }
+        };
+        if !debug_sections.tables.contains_key(&fixup_section_name) {
+            // Only the debug sections are relocated, e.g. reloc.CODE is
+            // not needed to read DWARF.
+            continue;
+        }
+        let count = reader.read_var_u32()?;
         for _ in 0..count {
-            let ty = reader.read_var_u32().unwrap();
-            let mut table_entry = debug_sections.tables.get_mut(&to_vec(fixup_section_name));
-            let table: &mut Vec<u8> = table_entry.unwrap();
-            let fixup_offset = reader.read_var_u32().unwrap() as usize;
-
-            let index = reader.read_var_u32().unwrap();
-            let target_offset = match ty {
-                5 => {
-                    if let SymbolKind::Data(segment, offset) = symbols[&index] {
-                        debug_sections.data_segment_offsets[segment as usize] + offset
-                    } else {
-                        panic!("unexpected symbol type");
+            let ty = reader.read_var_u32()?;
+            let reloc_type = match RELOC_TYPES.get(ty as usize) {
+                Some(reloc_type) => reloc_type,
+                None => return Err(Error::Relocation(format!("unexpected reloc type {}", ty))),
+            };
+            let fixup_offset = reader.read_var_u32()? as usize;
+            let index = reader.read_var_u32()?;
+            let target_addend = if reloc_type.has_addend() {
+                reader.read_var_i64()?
+            } else {
+                0
+            };
+
+            let symbol = linking.symbols.get(index as usize);
+            let symbol_name = match symbol {
+                _ if reloc_type.target == RelocTarget::TypeIndex => None,
+                Some(&Symbol {
+                    kind: SymbolKind::Section(section_index),
+                    ..
+                }) => debug_sections
+                    .tables_index
+                    .get(&(section_index as usize))
+                    .map(|name| String::from_utf8_lossy(name).into_owned()),
+                Some(symbol) => symbol.name.clone(),
+                None => None,
+            };
+            let symbol_error = |expected: &str| {
+                Error::Relocation(format!(
+                    "{}: symbol {} {} is {}, {} is expected",
+                    reloc_type.name,
+                    index,
+                    symbol
+                        .and_then(|symbol| symbol.name.as_deref())
+                        .unwrap_or(""),
+                    match symbol {
+                        Some(symbol) if symbol.is_undefined() => "undefined",
+                        Some(symbol) => symbol.kind.description(),
+                        None => "not found",
+                    },
+                    expected
+                ))
+            };
+            let symbol = symbol.map(|symbol| &symbol.kind);
+            let mut base = None;
+            let target_offset = match (reloc_type.target, symbol) {
+                // The type index is not a symbol.
+                (RelocTarget::TypeIndex, _) => index as u64,
+                (RelocTarget::FunctionIndex, Some(&SymbolKind::Function(func_index))) => {
+                    func_index as u64
+                }
+                (RelocTarget::TableIndex, Some(&SymbolKind::Function(func_index))) => {
+                    match debug_sections.func_table_indices.get(&func_index) {
+                        Some(&table_index) => table_index,
+                        None => {
+                            return Err(Error::Relocation(format!(
+                                "function {} is not in the table",
+                                func_index
+                            )))
+                        }
                     }
                 }
-                8 => {
-                    let func_index = func_indices.get(&index).unwrap();
-                    debug_sections.func_offsets[*func_index as usize] as u32 // function offset
+                (RelocTarget::FunctionIndex, _) | (RelocTarget::TableIndex, _) => {
+                    return Err(symbol_error("function"))
                 }
-                9 => 0, // section offset,
-                _ => panic!("unexpected reloc type"),
+                (RelocTarget::MemoryAddress, Some(&SymbolKind::Data(Some(ref data_ref))))
+                | (RelocTarget::MemoryAddressLocRel, Some(&SymbolKind::Data(Some(ref data_ref)))) => {
+                    match debug_sections
+                        .data_segment_offsets
+                        .get(data_ref.segment as usize)
+                    {
+                        Some(&SegmentOffset::Constant(segment_offset)) => {
+                            segment_offset.wrapping_add(data_ref.offset)
+                        }
+                        Some(&SegmentOffset::GlobalRelative {
+                            global_index,
+                            offset: segment_offset,
+                        }) => {
+                            base =
+                                Some(global_name(debug_sections, &linking.symbols, global_index));
+                            segment_offset.wrapping_add(data_ref.offset)
+                        }
+                        Some(&SegmentOffset::Passive) => {
+                            base = Some(format!("passive segment {}", data_ref.segment));
+                            data_ref.offset
+                        }
+                        None => {
+                            return Err(Error::Relocation(format!(
+                                "data segment {} was not found",
+                                data_ref.segment
+                            )))
+                        }
+                    }
+                }
+                (RelocTarget::MemoryAddress, _) | (RelocTarget::MemoryAddressLocRel, _) => {
+                    return Err(symbol_error("defined data"))
+                }
+                (RelocTarget::GlobalIndex, Some(&SymbolKind::Global(global_index))) => {
+                    global_index as u64
+                }
+                (RelocTarget::GlobalIndex, _) => return Err(symbol_error("global")),
+                (RelocTarget::FunctionOffset, Some(&SymbolKind::Function(func_index))) => {
+                    match debug_sections.func_offsets.get(func_index as usize) {
+                        Some(&func_offset) => func_offset as u64,
+                        None => {
+                            return Err(Error::Relocation(format!(
+                                "function {} was not found",
+                                func_index
+                            )))
+                        }
+                    }
+                }
+                (RelocTarget::FunctionOffset, _) => return Err(symbol_error("function")),
+                (RelocTarget::SectionOffset, Some(&SymbolKind::Section(section_index))) => {
+                    // The section symbols refer to the beginning of the
+                    // section contents.
+                    if !debug_sections
+                        .tables_index
+                        .contains_key(&(section_index as usize))
+                    {
+                        return Err(Error::Relocation(format!(
+                            "section {} was not found",
+                            section_index
+                        )));
+                    }
+                    0
+                }
+                (RelocTarget::SectionOffset, _) => return Err(symbol_error("section")),
+                (RelocTarget::TagIndex, Some(&SymbolKind::Tag(tag_index))) => tag_index as u64,
+                (RelocTarget::TagIndex, _) => return Err(symbol_error("tag")),
+                (RelocTarget::TableNumber, Some(&SymbolKind::Table(table_index))) => {
+                    table_index as u64
+                }
+                (RelocTarget::TableNumber, _) => return Err(symbol_error("table")),
             };
 
-            let target_addend = reader.read_var_u32().unwrap();
+            let table = debug_sections.tables.get_mut(&fixup_section_name).unwrap();
+            let size = reloc_type.encoding.size();
+            if fixup_offset + size > table.len() {
+                return Err(Error::Relocation(format!(
+                    "fixup offset 0x{:x} is out of {} bounds",
+                    fixup_offset,
+                    String::from_utf8_lossy(&fixup_section_name)
+                )));
+            }
 
-            let _old_offset = (table[fixup_offset + 0] as u32)
-                | ((table[fixup_offset + 1] as u32) << 8)
-                | ((table[fixup_offset + 2] as u32) << 16)
-                | ((table[fixup_offset + 3] as u32) << 24);
+            let fixup = &mut table[fixup_offset..fixup_offset + size];
+            let old_offset = reloc_type.encoding.read(fixup);
 
-            let offset = target_offset + target_addend;
-            table[fixup_offset + 0] = (offset & 0xFF) as u8;
-            table[fixup_offset + 1] = ((offset >> 8) & 0xFF) as u8;
-            table[fixup_offset + 2] = ((offset >> 16) & 0xFF) as u8;
-            table[fixup_offset + 3] = ((offset >> 24) & 0xFF) as u8;
+            let mut offset = target_offset.wrapping_add(target_addend as u64);
+            if reloc_type.target == RelocTarget::MemoryAddressLocRel {
+                // The debug sections are not loaded into memory, the fixup
+                // location is its offset in the section.
+                offset = offset.wrapping_sub(fixup_offset as u64);
+            }
+            reloc_type.encoding.write(fixup, offset);
 
-            // println!("{:x}: {:x} -> {:x} ({} {} t{})",
-            //   fixup_offset, _old_offset, offset,
-            //   str::from_utf8(fixup_section_name).unwrap(), index, ty);
+            entries.push(RelocEntry {
+                reloc_section: String::from_utf8_lossy(reloc_table_name).into_owned(),
+                section: String::from_utf8_lossy(&fixup_section_name).into_owned(),
+                type_name: reloc_type.name,
+                offset: fixup_offset,
+                symbol_index: index,
+                symbol_name,
+                addend: target_addend,
+                old_value: old_offset,
+                new_value: reloc_type.encoding.read(fixup),
+                base,
+            });
         }
     }
+    Ok(entries)
+}
+
+// Updates the relocation section after the sections removal, see
+// remap_linking_sections. Returns None if the target section was removed.
+pub(crate) fn remap_reloc_section(
+    reloc_table: &[u8],
+    section_map: &[Option<u32>],
+    symbol_map: &[Option<u32>],
+) -> Result<Option<Vec<u8>>> {
+    let mut reader = BinaryReader::new(reloc_table);
+    let section_index = reader.read_var_u32()?;
+    let section_index = match section_map.get(section_index as usize) {
+        Some(&Some(section_index)) => section_index,
+        _ => return Ok(None),
+    };
+    let count = reader.read_var_u32()?;
+    let mut result = Vec::new();
+    write_var_u32(&mut result, section_index as usize);
+    write_var_u32(&mut result, count as usize);
+    for _ in 0..count {
+        let ty = reader.read_var_u32()?;
+        let reloc_type = match RELOC_TYPES.get(ty as usize) {
+            Some(reloc_type) => reloc_type,
+            None => return Err(Error::Relocation(format!("unexpected reloc type {}", ty))),
+        };
+        let fixup_offset = reader.read_var_u32()?;
+        let mut index = reader.read_var_u32()?;
+        if reloc_type.target != RelocTarget::TypeIndex {
+            index = match symbol_map.get(index as usize) {
+                Some(&Some(index)) => index,
+                _ => {
+                    return Err(Error::Relocation(format!(
+                        "{}: symbol {} was removed",
+                        reloc_type.name, index
+                    )))
+                }
+            };
+        }
+        write_var_u32(&mut result, ty as usize);
+        write_var_u32(&mut result, fixup_offset as usize);
+        write_var_u32(&mut result, index as usize);
+        if reloc_type.has_addend() {
+            write_var_i64(&mut result, reader.read_var_i64()?);
+        }
+    }
+    Ok(Some(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RelocEncoding;
+
+    fn encode(encoding: RelocEncoding, value: u64) -> Vec<u8> {
+        let mut bytes = vec![0; encoding.size()];
+        encoding.write(&mut bytes, value);
+        assert_eq!(encoding.read(&bytes), value);
+        bytes
+    }
+
+    #[test]
+    fn padded_leb() {
+        let leb = RelocEncoding::Leb;
+        assert_eq!(encode(leb, 0), [0x80, 0x80, 0x80, 0x80, 0x00]);
+        assert_eq!(encode(leb, 127), [0xff, 0x80, 0x80, 0x80, 0x00]);
+        assert_eq!(encode(leb, 128), [0x80, 0x81, 0x80, 0x80, 0x00]);
+        assert_eq!(encode(leb, 16383), [0xff, 0xff, 0x80, 0x80, 0x00]);
+        assert_eq!(encode(leb, 16384), [0x80, 0x80, 0x81, 0x80, 0x00]);
+        assert_eq!(encode(leb, 0xffff_ffff), [0xff, 0xff, 0xff, 0xff, 0x0f]);
+        // The non-padded values are read too.
+        assert_eq!(leb.read(&[0x80, 0x01, 0xff, 0xff, 0xff]), 128);
+    }
+
+    #[test]
+    fn padded_sleb() {
+        let sleb = RelocEncoding::Sleb;
+        assert_eq!(encode(sleb, 63), [0xbf, 0x80, 0x80, 0x80, 0x00]);
+        assert_eq!(encode(sleb, 64), [0xc0, 0x80, 0x80, 0x80, 0x00]);
+        assert_eq!(encode(sleb, 128), [0x80, 0x81, 0x80, 0x80, 0x00]);
+        assert_eq!(encode(sleb, -1i64 as u64), [0xff, 0xff, 0xff, 0xff, 0x7f]);
+        assert_eq!(encode(sleb, -64i64 as u64), [0xc0, 0xff, 0xff, 0xff, 0x7f]);
+        assert_eq!(encode(sleb, -65i64 as u64), [0xbf, 0xff, 0xff, 0xff, 0x7f]);
+        assert_eq!(
+            encode(sleb, i32::MIN as i64 as u64),
+            [0x80, 0x80, 0x80, 0x80, 0x78]
+        );
+        assert_eq!(sleb.read(&[0x7f]), -1i64 as u64);
+    }
+
+    #[test]
+    fn padded_leb64() {
+        assert_eq!(
+            encode(RelocEncoding::Leb64, 128),
+            [0x80, 0x81, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00]
+        );
+        assert_eq!(
+            encode(RelocEncoding::Leb64, u64::MAX),
+            [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]
+        );
+        assert_eq!(
+            encode(RelocEncoding::Sleb64, -1i64 as u64),
+            [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]
+        );
+        assert_eq!(
+            encode(RelocEncoding::Sleb64, i64::MIN as u64),
+            [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f]
+        );
+        assert_eq!(
+            encode(RelocEncoding::Sleb64, i64::MAX as u64),
+            [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00]
+        );
+    }
+
+    #[test]
+    fn fixed_size() {
+        assert_eq!(
+            encode(RelocEncoding::I32, 0x1234_5678),
+            [0x78, 0x56, 0x34, 0x12]
+        );
+        assert_eq!(
+            encode(RelocEncoding::I64, 0x0102_0304_0506_0708),
+            [0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]
+        );
+    }
 }
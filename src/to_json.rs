@@ -1,51 +1,308 @@
-// Converts DebugLocInfo to JS source maps.
+// Converts DebugLocInfo to JS source maps, and the relocation information to
+// JSON.
 
 use dwarf::DebugLocInfo;
+use linking::{LinkingInfo, SymbolKind};
+use reloc::RelocEntry;
 use rustc_serialize::json::{Json, ToJson};
 use std::collections::BTreeMap;
 use std::str;
 use vlq::encode;
 
-pub fn convert_debug_info_to_json(di: &DebugLocInfo) -> Json {
+// Specifies how the rows without column information are mapped.
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum ColumnZero {
+    // Map to the beginning of the line.
+    #[default]
+    LineOnly,
+    // Emit unmapped segment.
+    Unmapped,
+}
+
+#[derive(Default)]
+pub struct SourceMapOptions {
+    // Emit only the rows that are marked as the statement beginning.
+    pub is_stmt_only: bool,
+    pub column_zero: ColumnZero,
+}
+
+pub fn convert_debug_info_to_json(di: &DebugLocInfo, options: &SourceMapOptions) -> Json {
     let mut buffer = Vec::new();
     let mut last_address = 0;
     let mut last_source_id = 0;
     let mut last_line = 1;
     let mut last_column = 1;
-    for loc in di.locations.iter() {
-        if loc.line == 0 || loc.column == 0 {
+    let mut last_name_id = 0;
+    // Nothing is mapped at the beginning, so there is no need to emit the
+    // unmapped segment.
+    let mut last_unmapped = true;
+    for (i, loc) in di.locations.iter().enumerate() {
+        // The compiler generated code (line 0) and the gaps after sequences
+        // are marked as unmapped, so the previous mapping is not extended
+        // over them.
+        let unmapped = loc.end_sequence
+            || loc.line == 0
+            || (loc.column == 0 && options.column_zero == ColumnZero::Unmapped);
+        if unmapped {
+            let next_at_same_address = di
+                .locations
+                .get(i + 1)
+                .is_some_and(|next| next.address == loc.address);
+            if last_unmapped || next_at_same_address {
+                continue;
+            }
+            let address_delta = loc.address as i64 - last_address;
+            encode(address_delta, &mut buffer).unwrap();
+            buffer.push(b',');
+
+            last_address = loc.address as i64;
+            last_unmapped = true;
+            continue;
+        }
+        if options.is_stmt_only && !loc.is_stmt {
             continue;
         }
+        // The column 0 means no column information, mapping it to the
+        // first column.
+        let column = if loc.column == 0 { 1 } else { loc.column };
         let address_delta = loc.address as i64 - last_address;
         encode(address_delta, &mut buffer).unwrap();
         let source_id_delta = loc.source_id as i64 - last_source_id;
         encode(source_id_delta, &mut buffer).unwrap();
         let line_delta = loc.line as i64 - last_line;
         encode(line_delta, &mut buffer).unwrap();
-        let column_delta = loc.column as i64 - last_column;
+        let column_delta = column as i64 - last_column;
         encode(column_delta, &mut buffer).unwrap();
+        if let Some(name_id) = loc.name_id {
+            let name_id_delta = name_id as i64 - last_name_id;
+            encode(name_id_delta, &mut buffer).unwrap();
+            last_name_id = name_id as i64;
+        }
         buffer.push(b',');
 
         last_address = loc.address as i64;
         last_source_id = loc.source_id as i64;
         last_line = loc.line as i64;
-        last_column = loc.column as i64;
+        last_column = column as i64;
+        last_unmapped = false;
     }
 
-    if di.locations.len() > 0 {
+    if buffer.last() == Some(&b',') {
         buffer.pop();
     }
 
     let mappings = str::from_utf8(&buffer).unwrap();
-    let names: Vec<String> = Vec::new();
 
     let mut root = BTreeMap::new();
     root.insert("version".to_string(), 3.to_json());
     root.insert("sources".to_string(), di.sources.to_json());
-    root.insert("names".to_string(), names.to_json());
+    root.insert("names".to_string(), di.names.to_json());
     root.insert("mappings".to_string(), mappings.to_json());
     if let Some(ref sources_content) = di.sources_content {
         root.insert("sourcesContent".to_string(), sources_content.to_json());
     }
     Json::Object(root)
 }
+
+fn convert_linking_to_json(linking: &LinkingInfo) -> Json {
+    let mut symbols = Vec::new();
+    for symbol in linking.symbols.iter() {
+        let mut entry = BTreeMap::new();
+        entry.insert("kind".to_string(), symbol.kind.description().to_json());
+        if let Some(ref name) = symbol.name {
+            entry.insert("name".to_string(), name.to_json());
+        }
+        let flags: Vec<Json> = symbol
+            .flag_names()
+            .iter()
+            .map(|flag| flag.to_json())
+            .collect();
+        entry.insert("flags".to_string(), Json::Array(flags));
+        match symbol.kind {
+            SymbolKind::Function(index)
+            | SymbolKind::Global(index)
+            | SymbolKind::Tag(index)
+            | SymbolKind::Table(index) => {
+                entry.insert("index".to_string(), index.to_json());
+            }
+            SymbolKind::Data(Some(ref data_ref)) => {
+                entry.insert("segment".to_string(), data_ref.segment.to_json());
+                entry.insert("offset".to_string(), data_ref.offset.to_json());
+                entry.insert("size".to_string(), data_ref.size.to_json());
+            }
+            SymbolKind::Data(None) => {}
+            SymbolKind::Section(index) => {
+                entry.insert("section".to_string(), index.to_json());
+            }
+        }
+        symbols.push(Json::Object(entry));
+    }
+
+    let mut segments = Vec::new();
+    for segment in linking.segments.iter() {
+        let mut entry = BTreeMap::new();
+        entry.insert("name".to_string(), segment.name.to_json());
+        entry.insert(
+            "alignment".to_string(),
+            (1u64 << segment.alignment).to_json(),
+        );
+        entry.insert("flags".to_string(), segment.flags.to_json());
+        segments.push(Json::Object(entry));
+    }
+
+    let mut init_funcs = Vec::new();
+    for init_func in linking.init_funcs.iter() {
+        let mut entry = BTreeMap::new();
+        entry.insert("priority".to_string(), init_func.priority.to_json());
+        entry.insert("symbol".to_string(), init_func.symbol_index.to_json());
+        init_funcs.push(Json::Object(entry));
+    }
+
+    let mut comdats = Vec::new();
+    for comdat in linking.comdats.iter() {
+        let mut entries = Vec::new();
+        for comdat_entry in comdat.entries.iter() {
+            let mut entry = BTreeMap::new();
+            entry.insert("kind".to_string(), comdat_entry.kind.to_json());
+            entry.insert("index".to_string(), comdat_entry.index.to_json());
+            entries.push(Json::Object(entry));
+        }
+        let mut entry = BTreeMap::new();
+        entry.insert("name".to_string(), comdat.name.to_json());
+        entry.insert("flags".to_string(), comdat.flags.to_json());
+        entry.insert("entries".to_string(), Json::Array(entries));
+        comdats.push(Json::Object(entry));
+    }
+
+    let mut root = BTreeMap::new();
+    root.insert("version".to_string(), linking.version.to_json());
+    root.insert("symbols".to_string(), Json::Array(symbols));
+    root.insert("segments".to_string(), Json::Array(segments));
+    root.insert("initFuncs".to_string(), Json::Array(init_funcs));
+    root.insert("comdats".to_string(), Json::Array(comdats));
+    Json::Object(root)
+}
+
+// The relocation values are in the same units as stored, e.g. function
+// offsets relative to the code section content.
+pub fn convert_relocations_to_json(linking: &LinkingInfo, relocations: &[RelocEntry]) -> Json {
+    let mut entries = Vec::new();
+    for relocation in relocations.iter() {
+        let mut entry = BTreeMap::new();
+        entry.insert(
+            "relocSection".to_string(),
+            relocation.reloc_section.to_json(),
+        );
+        entry.insert("section".to_string(), relocation.section.to_json());
+        entry.insert("type".to_string(), relocation.type_name.to_json());
+        entry.insert("offset".to_string(), relocation.offset.to_json());
+        entry.insert("symbol".to_string(), relocation.symbol_index.to_json());
+        if let Some(ref name) = relocation.symbol_name {
+            entry.insert("symbolName".to_string(), name.to_json());
+        }
+        entry.insert("addend".to_string(), relocation.addend.to_json());
+        entry.insert("before".to_string(), relocation.old_value.to_json());
+        entry.insert("after".to_string(), relocation.new_value.to_json());
+        if let Some(ref base) = relocation.base {
+            entry.insert("base".to_string(), base.to_json());
+        }
+        entries.push(Json::Object(entry));
+    }
+
+    let mut root = BTreeMap::new();
+    root.insert("linking".to_string(), convert_linking_to_json(linking));
+    root.insert("relocations".to_string(), Json::Array(entries));
+    Json::Object(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dwarf::DebugLoc;
+
+    fn loc(address: u64, line: u32, column: u32, is_stmt: bool) -> DebugLoc {
+        DebugLoc {
+            address,
+            source_id: 0,
+            line,
+            column,
+            name_id: None,
+            is_stmt,
+            basic_block: false,
+            prologue_end: false,
+            epilogue_begin: false,
+            end_sequence: false,
+            isa: 0,
+            discriminator: 0,
+        }
+    }
+
+    fn end_sequence(address: u64) -> DebugLoc {
+        DebugLoc {
+            end_sequence: true,
+            ..loc(address, 0, 0, false)
+        }
+    }
+
+    fn mappings(locations: Vec<DebugLoc>, options: &SourceMapOptions) -> String {
+        let mut di = DebugLocInfo::default();
+        di.sources = vec!["a.c".to_string()];
+        di.locations = locations;
+        let json = convert_debug_info_to_json(&di, options);
+        json.find("mappings")
+            .unwrap()
+            .as_string()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn unmapped_segment_at_same_address() {
+        let options = SourceMapOptions::default();
+        let locations = vec![loc(0, 1, 1, true), end_sequence(4), loc(4, 2, 1, true)];
+        assert_eq!(mappings(locations, &options), "AAAA,IACA");
+        let locations = vec![loc(0, 1, 1, true), end_sequence(4), loc(8, 2, 1, true)];
+        assert_eq!(mappings(locations, &options), "AAAA,I,IACA");
+    }
+
+    #[test]
+    fn no_leading_unmapped_segment() {
+        let options = SourceMapOptions::default();
+        let locations = vec![end_sequence(0), loc(2, 0, 1, true), loc(4, 1, 1, true)];
+        assert_eq!(mappings(locations, &options), "IAAA");
+        assert_eq!(mappings(vec![end_sequence(0)], &options), "");
+    }
+
+    #[test]
+    fn column_zero() {
+        let locations = || vec![loc(0, 1, 0, true), loc(4, 2, 3, true), end_sequence(8)];
+        let options = SourceMapOptions::default();
+        assert_eq!(mappings(locations(), &options), "AAAA,IACE,I");
+        let options = SourceMapOptions {
+            column_zero: ColumnZero::Unmapped,
+            ..SourceMapOptions::default()
+        };
+        assert_eq!(mappings(locations(), &options), "IACE,I");
+        let locations = vec![loc(0, 1, 1, true), loc(4, 2, 0, true), end_sequence(8)];
+        assert_eq!(mappings(locations, &options), "AAAA,I");
+    }
+
+    #[test]
+    fn is_stmt_only() {
+        let locations = || {
+            vec![
+                loc(0, 1, 1, true),
+                loc(4, 2, 1, false),
+                loc(8, 3, 1, true),
+                end_sequence(12),
+            ]
+        };
+        let options = SourceMapOptions::default();
+        assert_eq!(mappings(locations(), &options), "AAAA,IACA,IACA,I");
+        let options = SourceMapOptions {
+            is_stmt_only: true,
+            ..SourceMapOptions::default()
+        };
+        assert_eq!(mappings(locations(), &options), "AAAA,QAEA,I");
+    }
+}
@@ -3,224 +3,1328 @@
 use std::collections::HashMap;
 use std::io::Write;
 
-use wasmparser::{ImportSectionEntryType, Operator, Parser, ParserInput, ParserState, SectionCode,
-                 WasmDecoder};
+use error::{Error, Result};
+use linking::remap_linking_sections;
+use reloc::remap_reloc_section;
+use wasmparser::{BinaryReader, Operator};
 
-fn is_reloc_debug_section_name(name: &[u8]) -> bool {
-    return name.len() >= 13 && &name[0..13] == b"reloc..debug_";
+const WASM_MAGIC_NUMBER: u32 = 0x6d73_6100;
+const WASM_VERSION: u32 = 1;
+
+// The relocation sections are found by the prefix only, their target is
+// specified by the section index.
+fn is_reloc_section_name(name: &[u8]) -> bool {
+    name.starts_with(b"reloc.")
 }
 
 fn is_debug_section_name(name: &[u8]) -> bool {
-    return name.len() >= 7 && &name[0..7] == b".debug_";
+    name.starts_with(b".debug_")
 }
 
 fn is_linking_section_name(name: &[u8]) -> bool {
-    return name == b"linking";
+    name == b"linking"
 }
 
 fn is_source_mapping_section_name(name: &[u8]) -> bool {
-    return name == b"sourceMappingURL";
+    name == b"sourceMappingURL"
+}
+
+fn is_external_debug_info_section_name(name: &[u8]) -> bool {
+    name == b"external_debug_info"
+}
+
+// The debug file split from the module describes the module code in this
+// section, see create_debug_file.
+const CODE_LAYOUT_SECTION_NAME: &str = "wasm-dwarf.code_layout";
+const CODE_LAYOUT_VERSION: u32 = 1;
+
+fn is_code_layout_section_name(name: &[u8]) -> bool {
+    name == CODE_LAYOUT_SECTION_NAME.as_bytes()
 }
 
 pub struct DebugSections {
     pub tables: HashMap<Vec<u8>, Vec<u8>>,
+    // Names of all sections by the section index, the known sections are
+    // named as by llvm-objdump, e.g. "CODE".
     pub tables_index: HashMap<usize, Vec<u8>>,
     pub reloc_tables: HashMap<Vec<u8>, Vec<u8>>,
     pub linking: Option<Vec<u8>>,
+    // Offset of the code section content, the DWARF addresses are relative
+    // to it. It is 0 if the module has no functions.
     pub code_content: usize,
+    pub code_size: usize,
+    pub has_code: bool,
+    pub imports: Vec<Import>,
     pub func_offsets: Vec<usize>,
-    pub data_segment_offsets: Vec<u32>,
+    pub data_segment_offsets: Vec<SegmentOffset>,
+    // Slots of the functions in the table 0, as initialized by the element
+    // segments.
+    pub func_table_indices: HashMap<u32, u64>,
+    pub source_mapping_url: Option<String>,
+    // URL of the file with the debug sections split from the module.
+    pub external_debug_info: Option<String>,
+    // Malformed optional sections that were ignored.
+    pub warnings: Vec<Error>,
+}
+
+fn known_section_name(id: u32) -> &'static [u8] {
+    match id {
+        1 => b"TYPE",
+        2 => b"IMPORT",
+        3 => b"FUNCTION",
+        4 => b"TABLE",
+        5 => b"MEMORY",
+        6 => b"GLOBAL",
+        7 => b"EXPORT",
+        8 => b"START",
+        9 => b"ELEM",
+        10 => b"CODE",
+        11 => b"DATA",
+        12 => b"DATACOUNT",
+        13 => b"TAG",
+        _ => b"UNKNOWN",
+    }
+}
+
+fn parse_error(message: &str, offset: usize) -> Error {
+    Error::WasmParse {
+        message: String::from(message),
+        offset,
+    }
+}
+
+// Reads unsigned LEB128 value of up to 64 bits, e.g. memory64 limits or
+// addresses.
+pub(crate) fn read_var_u64(reader: &mut BinaryReader) -> Result<u64> {
+    let mut result = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = reader.read_u8()?;
+        if shift == 63 && byte > 1 {
+            return Err(parse_error(
+                "invalid var_u64",
+                reader.current_position() - 1,
+            ));
+        }
+        result |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+        shift += 7;
+    }
+}
+
+fn skip_limits(reader: &mut BinaryReader) -> Result<()> {
+    let flags = reader.read_var_u32()?;
+    // The memory64 limits are encoded as 64-bit values.
+    read_var_u64(reader)?;
+    if flags & 0x1 != 0 {
+        read_var_u64(reader)?;
+    }
+    Ok(())
+}
+
+// Imported entity, the kind uses the external kind encoding, e.g. 0 for
+// functions.
+pub struct Import {
+    pub module: String,
+    pub field: String,
+    pub kind: u8,
+}
+
+fn read_imports(reader: &mut BinaryReader) -> Result<Vec<Import>> {
+    let count = reader.read_var_u32()?;
+    let mut imports = Vec::new();
+    for _ in 0..count {
+        let module = String::from_utf8_lossy(reader.read_string()?).into_owned();
+        let field = String::from_utf8_lossy(reader.read_string()?).into_owned();
+        let kind_offset = reader.current_position();
+        let kind = reader.read_u8()?;
+        match kind {
+            0 /* function */ => {
+                reader.read_var_u32()?;
+            }
+            1 /* table */ => {
+                reader.read_u8()?;
+                skip_limits(reader)?;
+            }
+            2 /* memory */ => skip_limits(reader)?,
+            3 /* global */ => {
+                reader.read_u8()?;
+                reader.read_u8()?;
+            }
+            4 /* tag */ => {
+                reader.read_u8()?;
+                reader.read_var_u32()?;
+            }
+            _ => return Err(parse_error("unknown import kind", kind_offset)),
+        }
+        imports.push(Import {
+            module,
+            field,
+            kind: kind as u8,
+        });
+    }
+    Ok(imports)
+}
+
+// Placement of the data segment in the memory.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SegmentOffset {
+    Constant(u64),
+    // The PIC modules place the segments relative to the imported global,
+    // e.g. __memory_base.
+    GlobalRelative { global_index: u32, offset: u64 },
+    // The passive segments are copied by memory.init at runtime.
+    Passive,
+}
+
+// Evaluates the segment offset expression, the i64 operators are used by
+// memory64 modules.
+fn read_offset_expression(reader: &mut BinaryReader) -> Result<SegmentOffset> {
+    let offset = reader.current_position();
+    let unsupported = || parse_error("unsupported data segment offset expression", offset);
+    let mut stack = Vec::new();
+    loop {
+        match reader.read_operator()? {
+            Operator::I32Const { value } => {
+                stack.push(SegmentOffset::Constant(value as u32 as u64))
+            }
+            Operator::I64Const { value } => stack.push(SegmentOffset::Constant(value as u64)),
+            Operator::GetGlobal { global_index } => stack.push(SegmentOffset::GlobalRelative {
+                global_index,
+                offset: 0,
+            }),
+            Operator::I32Add | Operator::I64Add => {
+                let (b, a) = match (stack.pop(), stack.pop()) {
+                    (Some(b), Some(a)) => (b, a),
+                    _ => return Err(unsupported()),
+                };
+                let sum = match (a, b) {
+                    (SegmentOffset::Constant(a), SegmentOffset::Constant(b)) => {
+                        SegmentOffset::Constant(a.wrapping_add(b))
+                    }
+                    (
+                        SegmentOffset::GlobalRelative {
+                            global_index,
+                            offset,
+                        },
+                        SegmentOffset::Constant(c),
+                    )
+                    | (
+                        SegmentOffset::Constant(c),
+                        SegmentOffset::GlobalRelative {
+                            global_index,
+                            offset,
+                        },
+                    ) => SegmentOffset::GlobalRelative {
+                        global_index,
+                        offset: offset.wrapping_add(c),
+                    },
+                    _ => return Err(unsupported()),
+                };
+                stack.push(sum);
+            }
+            Operator::End => break,
+            _ => return Err(unsupported()),
+        }
+    }
+    match (stack.pop(), stack.is_empty()) {
+        (Some(value), true) => Ok(value),
+        _ => Err(unsupported()),
+    }
+}
+
+// Reads the functions placed into the table 0 by the element segments. The
+// segments with expressions are not used for the functions tables and are
+// skipped together with the rest of the section.
+fn read_func_table_indices(
+    reader: &mut BinaryReader,
+    section_end: usize,
+    func_table_indices: &mut HashMap<u32, u64>,
+) -> Result<()> {
+    let count = reader.read_var_u32()?;
+    for _ in 0..count {
+        let flags = reader.read_var_u32()?;
+        let (table_index, table_offset) = match flags {
+            0 => (0, read_offset_expression(reader)?),
+            1 | 3 => {
+                // Passive or declarative segment with the element kind.
+                reader.read_u8()?;
+                (0, SegmentOffset::Passive)
+            }
+            2 => {
+                let table_index = reader.read_var_u32()?;
+                let table_offset = read_offset_expression(reader)?;
+                reader.read_u8()?;
+                (table_index, table_offset)
+            }
+            _ => break,
+        };
+        let elements_count = reader.read_var_u32()?;
+        for i in 0..elements_count {
+            let func_index = reader.read_var_u32()?;
+            // The functions placed relative to __table_base have no known slot.
+            if let (0, SegmentOffset::Constant(table_offset)) = (table_index, table_offset) {
+                func_table_indices
+                    .entry(func_index)
+                    .or_insert(table_offset + i as u64);
+            }
+        }
+    }
+    let position = reader.current_position();
+    reader.read_bytes(section_end.saturating_sub(position))?;
+    Ok(())
+}
+
+// Reads the URL stored as a string in the custom section data.
+fn read_url(data: &[u8], offset: usize) -> Result<String> {
+    let mut reader = BinaryReader::new(data);
+    let url = match reader.read_string() {
+        Ok(url) => url,
+        Err(_) => return Err(parse_error("malformed URL section", offset)),
+    };
+    if !reader.eof() {
+        return Err(parse_error("unexpected data after the URL", offset));
+    }
+    Ok(String::from_utf8_lossy(url).into_owned())
+}
+
+// Code section location and the function bodies offsets of the stripped
+// module, stored in its debug file.
+struct CodeLayout {
+    code_content: usize,
+    code_size: usize,
+    func_offsets: Vec<usize>,
+}
+
+fn read_code_layout(data: &[u8], offset: usize) -> Result<CodeLayout> {
+    let mut reader = BinaryReader::new(data);
+    if reader.read_var_u32()? != CODE_LAYOUT_VERSION {
+        return Err(parse_error("unsupported code layout version", offset));
+    }
+    let code_content = reader.read_var_u32()? as usize;
+    let code_size = reader.read_var_u32()? as usize;
+    let count = reader.read_var_u32()?;
+    let mut func_offsets = Vec::new();
+    for _ in 0..count {
+        func_offsets.push(reader.read_var_u32()? as usize);
+    }
+    if !reader.eof() {
+        return Err(parse_error("unexpected data after the code layout", offset));
+    }
+    Ok(CodeLayout {
+        code_content,
+        code_size,
+        func_offsets,
+    })
 }
 
 impl DebugSections {
-    pub fn read_sections(wasm: &[u8]) -> DebugSections {
-        let mut parser = Parser::new(wasm);
-        let mut input = ParserInput::Default;
-        let mut current_section_name = None;
+    // Reads the stripped module together with its debug file, as written by
+    // create_debug_file.
+    pub fn read_split_sections(wasm: &[u8], debug_wasm: &[u8]) -> Result<DebugSections> {
+        let mut debug_sections = DebugSections::read_sections(wasm)?;
+        let debug_file = DebugSections::read_sections(debug_wasm)?;
+        if debug_file.code_content != debug_sections.code_content
+            || debug_file.code_size != debug_sections.code_size
+            || debug_file.func_offsets != debug_sections.func_offsets
+        {
+            return Err(Error::DebugFileMismatch);
+        }
+        debug_sections.tables = debug_file.tables;
+        Ok(debug_sections)
+    }
+
+    pub fn read_sections(wasm: &[u8]) -> Result<DebugSections> {
+        let mut reader = BinaryReader::new(wasm);
+        if reader.read_u32()? != WASM_MAGIC_NUMBER {
+            return Err(parse_error("bad magic number", 0));
+        }
+        if reader.read_u32()? != WASM_VERSION {
+            return Err(parse_error("bad version number", 4));
+        }
+
         let mut linking: Option<Vec<u8>> = None;
         let mut tables = HashMap::new();
         let mut tables_index = HashMap::new();
         let mut reloc_tables = HashMap::new();
         let mut code_content: Option<usize> = None;
+        let mut code_size = 0;
+        let mut imports = Vec::new();
         let mut func_offsets = Vec::new();
         let mut data_segment_offsets = Vec::new();
+        let mut func_table_indices = HashMap::new();
+        let mut source_mapping_url = None;
+        let mut external_debug_info = None;
+        let mut code_layout = None;
+        let mut warnings = Vec::new();
         let mut section_index = 0;
-        let mut data_copy = None;
-
-        loop {
-            let offset = parser.current_position();
-            if let ParserInput::SkipFunctionBody = input {
-                // Saw function body start, saving as func_offset
-                func_offsets.push(offset - code_content.unwrap());
-            }
-
-            let state = parser.read_with_input(input);
-            match *state {
-                ParserState::EndWasm => break,
-                ParserState::Error(err) => panic!("Error: {:?}", err),
-                ParserState::BeginSection {
-                    code: SectionCode::Custom { ref name, .. },
-                    ..
-                } if is_debug_section_name(name) || is_reloc_debug_section_name(name)
-                    || is_linking_section_name(name)
-                    || is_source_mapping_section_name(name) =>
-                {
-                    let mut name_copy = Vec::new();
-                    name_copy.extend_from_slice(name);
-                    current_section_name = Some(name_copy);
-                    data_copy = Some(Vec::new());
-                    input = ParserInput::ReadSectionRawData;
-                }
-                ParserState::SectionRawData(ref data) => {
-                    data_copy.as_mut().unwrap().extend_from_slice(data);
-                    input = ParserInput::Default;
-                }
-                ParserState::BeginSection {
-                    code: SectionCode::Import,
-                    ..
-                }
-                | ParserState::BeginSection {
-                    code: SectionCode::Data,
-                    ..
-                }
-                | ParserState::BeginSection {
-                    code: SectionCode::Code,
-                    ..
-                } => {
-                    input = ParserInput::Default;
-                }
-                ParserState::BeginFunctionBody { .. } => {
-                    if code_content.is_none() {
-                        code_content = Some(offset);
+
+        while !reader.eof() {
+            let section_offset = reader.current_position();
+            let id = reader.read_u8()?;
+            let size = reader.read_var_u32()? as usize;
+            let payload_start = reader.current_position();
+            if size > reader.bytes_remaining() {
+                return Err(parse_error("section is out of bounds", section_offset));
+            }
+            let payload_end = payload_start + size;
+            if id != 0 {
+                tables_index.insert(section_index, known_section_name(id).to_vec());
+            }
+            match id {
+                0 /* custom */ => {
+                    let name = reader.read_string()?;
+                    if reader.current_position() > payload_end {
+                        return Err(parse_error("section name is out of bounds", payload_start));
+                    }
+                    let data = reader.read_bytes(payload_end - reader.current_position())?;
+                    tables_index.insert(section_index, name.to_vec());
+                    if is_debug_section_name(name) {
+                        tables.insert(name.to_vec(), data.to_vec());
+                    } else if is_reloc_section_name(name) {
+                        reloc_tables.insert(name.to_vec(), data.to_vec());
+                    } else if is_linking_section_name(name) {
+                        linking = Some(data.to_vec());
+                    } else if is_source_mapping_section_name(name) {
+                        match read_url(data, payload_start) {
+                            Ok(url) => source_mapping_url = Some(url),
+                            Err(err) => warnings.push(err),
+                        }
+                    } else if is_external_debug_info_section_name(name) {
+                        match read_url(data, payload_start) {
+                            Ok(url) => external_debug_info = Some(url),
+                            Err(err) => warnings.push(err),
+                        }
+                    } else if is_code_layout_section_name(name) {
+                        code_layout = Some(read_code_layout(data, payload_start)?);
                     }
-                    input = ParserInput::SkipFunctionBody;
                 }
-                ParserState::ImportSectionEntry {
-                    ty: ImportSectionEntryType::Function(..),
-                    ..
-                } => {
-                    func_offsets.push(0); // include imports?
-                    input = ParserInput::Default;
+                2 /* import */ => {
+                    imports = read_imports(&mut reader)?;
+                    // The imported functions have no code.
+                    let count = imports.iter().filter(|import| import.kind == 0).count();
+                    func_offsets.extend((0..count).map(|_| 0));
                 }
-                ParserState::BeginSection { .. } => {
-                    input = ParserInput::SkipSection;
+                9 /* element */ => {
+                    read_func_table_indices(&mut reader, payload_end, &mut func_table_indices)?;
                 }
-                ParserState::EndSection => {
-                    section_index += 1;
-
-                    if data_copy.is_some() {
-                        let mut name_copy = Vec::new();
-                        name_copy.extend_from_slice(current_section_name.as_ref().unwrap());
-                        tables_index.insert(section_index, name_copy);
-
-                        let section_name = current_section_name.take().unwrap();
-                        let data = data_copy.take().unwrap();
-                        if is_debug_section_name(&section_name) {
-                            tables.insert(section_name, data);
-                        } else if is_reloc_debug_section_name(&section_name) {
-                            reloc_tables.insert(section_name, data);
-                        } else {
-                            assert!(is_linking_section_name(&section_name));
-                            linking = Some(data);
-                        }
+                10 /* code */ => {
+                    let count = reader.read_var_u32()?;
+                    if count > 0 {
+                        code_content = Some(payload_start);
+                        code_size = size;
+                    }
+                    for _ in 0..count {
+                        let body_size = reader.read_var_u32()? as usize;
+                        func_offsets.push(reader.current_position() - payload_start);
+                        reader.read_bytes(body_size)?;
                     }
-                    input = ParserInput::Default;
                 }
-                ParserState::InitExpressionOperator(ref op) => {
-                    if let Operator::I32Const { value } = op {
-                        data_segment_offsets.push(*value as u32);
-                    } else {
-                        panic!("Unexpected init expression operator");
+                11 /* data */ => {
+                    let count = reader.read_var_u32()?;
+                    for _ in 0..count {
+                        let segment_offset = reader.current_position();
+                        let segment = match reader.read_var_u32()? {
+                            0 => read_offset_expression(&mut reader)?,
+                            1 => SegmentOffset::Passive,
+                            2 => {
+                                // Memory index.
+                                reader.read_var_u32()?;
+                                read_offset_expression(&mut reader)?
+                            }
+                            _ => {
+                                return Err(parse_error(
+                                    "unsupported data segment kind",
+                                    segment_offset,
+                                ))
+                            }
+                        };
+                        data_segment_offsets.push(segment);
+                        let data_size = reader.read_var_u32()? as usize;
+                        reader.read_bytes(data_size)?;
                     }
-                    input = ParserInput::Default;
                 }
                 _ => {
-                    input = ParserInput::Default;
+                    reader.read_bytes(size)?;
                 }
             }
+            if reader.current_position() != payload_end {
+                return Err(parse_error("section size mismatch", section_offset));
+            }
+            section_index += 1;
         }
-        DebugSections {
+        if let (None, Some(code_layout)) = (code_content, code_layout) {
+            // The debug file has no code, the layout of the stripped module
+            // code is used instead.
+            code_content = Some(code_layout.code_content).filter(|_| code_layout.code_size > 0);
+            code_size = code_layout.code_size;
+            func_offsets = code_layout.func_offsets;
+        }
+        Ok(DebugSections {
             tables,
             tables_index,
             reloc_tables,
             linking,
-            code_content: code_content.unwrap(),
+            code_content: code_content.unwrap_or(0),
+            code_size,
+            has_code: code_content.is_some(),
+            imports,
             func_offsets,
             data_segment_offsets,
-        }
+            func_table_indices,
+            source_mapping_url,
+            external_debug_info,
+            warnings,
+        })
     }
 }
 
-pub fn remove_debug_sections(wasm: &[u8], write: &mut Write) {
-    let mut parser = Parser::new(wasm);
-    let mut input = ParserInput::Default;
-    let mut last_written = 0;
-    let mut skipping_section = false;
+pub(crate) fn write_var_u32(buf: &mut Vec<u8>, n: usize) {
+    write_padded_var_u32(buf, n, 1);
+}
+
+// Writes LEB128 value padded to at least the given number of bytes.
+fn write_padded_var_u32(buf: &mut Vec<u8>, n: usize, min_len: usize) {
+    let mut i = n;
+    let mut len = 1;
+    while i >= 0x80 || len < min_len {
+        buf.push(0x80 | (i & 0x7f) as u8);
+        i >>= 7;
+        len += 1;
+    }
+    buf.push(i as u8);
+}
+
+pub(crate) fn write_var_i64(buf: &mut Vec<u8>, n: i64) {
+    let mut i = n;
     loop {
-        let offset = parser.current_position();
-        let state = parser.read_with_input(input);
-        match *state {
-            ParserState::EndWasm => break,
-            ParserState::Error(err) => panic!("Error: {:?}", err),
-            ParserState::BeginSection {
-                code: SectionCode::Custom { ref name, .. },
-                ..
-            } if is_debug_section_name(name) || is_reloc_debug_section_name(name)
-                || is_linking_section_name(name) =>
+        let byte = (i & 0x7f) as u8;
+        i >>= 7;
+        if (i == 0 && byte & 0x40 == 0) || (i == -1 && byte & 0x40 != 0) {
+            buf.push(byte);
+            return;
+        }
+        buf.push(0x80 | byte);
+    }
+}
+
+pub(crate) fn write_string(buf: &mut Vec<u8>, s: &[u8]) {
+    write_var_u32(buf, s.len());
+    buf.extend_from_slice(s);
+}
+
+// Matches the section name against the pattern, where "*" matches any
+// sequence of characters and "?" matches one character.
+pub fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|i| glob_match(rest, &name[i..])),
+        Some((&c, rest)) => match name.split_first() {
+            Some((&n, name_rest)) if c == b'?' || c == n => glob_match(rest, name_rest),
+            _ => false,
+        },
+    }
+}
+
+// Section of the module, the custom sections data follows the name.
+pub struct Section {
+    pub id: u8,
+    // The known sections have no name.
+    pub name: Vec<u8>,
+    pub data: Vec<u8>,
+    // Length of the section size encoding in the original module, the
+    // padding is kept so the code section stays at the same offset.
+    size_len: usize,
+}
+
+impl Section {
+    pub fn custom(name: &str, data: Vec<u8>) -> Section {
+        Section {
+            id: 0,
+            name: name.as_bytes().to_vec(),
+            data,
+            size_len: 1,
+        }
+    }
+
+    pub fn is_custom(&self) -> bool {
+        self.id == 0
+    }
+
+    // The custom sections are named by their name, the known sections as by
+    // llvm-objdump, e.g. "CODE".
+    fn has_name(&self, name: &str) -> bool {
+        if self.is_custom() {
+            self.name == name.as_bytes()
+        } else {
+            known_section_name(self.id as u32) == name.as_bytes()
+        }
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let mut payload = Vec::new();
+        if self.is_custom() {
+            write_string(&mut payload, &self.name);
+        }
+        payload.extend_from_slice(&self.data);
+        buf.push(self.id);
+        write_padded_var_u32(buf, payload.len(), self.size_len);
+        buf.extend_from_slice(&payload);
+    }
+}
+
+// Field of the producers section with its (name, version) pairs.
+type ProducersField = (Vec<u8>, Vec<(Vec<u8>, Vec<u8>)>);
+
+fn read_producers(data: &[u8]) -> Result<Vec<ProducersField>> {
+    let mut reader = BinaryReader::new(data);
+    let mut fields = Vec::new();
+    if data.is_empty() {
+        return Ok(fields);
+    }
+    let count = reader.read_var_u32()?;
+    for _ in 0..count {
+        let field = reader.read_string()?.to_vec();
+        let values_count = reader.read_var_u32()?;
+        let mut values = Vec::new();
+        for _ in 0..values_count {
+            let name = reader.read_string()?.to_vec();
+            let version = reader.read_string()?.to_vec();
+            values.push((name, version));
+        }
+        fields.push((field, values));
+    }
+    Ok(fields)
+}
+
+pub enum StripProfile {
+    // The DWARF sections and their relocations, the linking section and the
+    // code and data relocations are kept.
+    DebugOnly,
+    // All custom sections except the name section.
+    AllCustom,
+}
+
+// Selects the custom sections to strip: the sections of the profile and the
+// sections matching the remove patterns, unless they match the keep
+// patterns.
+#[derive(Default)]
+pub struct StripOptions {
+    pub profile: Option<StripProfile>,
+    pub remove: Vec<String>,
+    pub keep: Vec<String>,
+}
+
+impl StripOptions {
+    pub fn removes(&self, name: &[u8]) -> bool {
+        let by_profile = match self.profile {
+            Some(StripProfile::DebugOnly) => is_debug_section_name(name),
+            Some(StripProfile::AllCustom) => name != b"name",
+            None => false,
+        };
+        (by_profile
+            || self
+                .remove
+                .iter()
+                .any(|pattern| glob_match(pattern.as_bytes(), name)))
+            && !self
+                .keep
+                .iter()
+                .any(|pattern| glob_match(pattern.as_bytes(), name))
+    }
+}
+
+pub struct RemovedSection {
+    pub name: String,
+    // Size of the section including its header.
+    pub size: usize,
+}
+
+#[derive(Default)]
+pub struct StripReport {
+    pub removed: Vec<RemovedSection>,
+    // The module size difference, including the updated linking and
+    // relocation sections.
+    pub bytes_saved: usize,
+}
+
+// Where the moved custom sections are placed.
+pub enum SectionPosition {
+    Before(String),
+    After(String),
+    End,
+}
+
+// Module sections in the file order, used to rewrite the custom sections.
+//
+// Usage:
+//
+//     let mut sections = SectionList::parse(&wasm)?;
+//     sections.strip(&StripOptions {
+//         profile: Some(StripProfile::DebugOnly),
+//         ..StripOptions::default()
+//     })?;
+//     sections.add_producer("processed-by", "wasm-dwarf", "0.3.0")?;
+//     let wasm = sections.encode()?;
+pub struct SectionList {
+    pub sections: Vec<Section>,
+}
+
+impl SectionList {
+    pub fn parse(wasm: &[u8]) -> Result<SectionList> {
+        let mut reader = BinaryReader::new(wasm);
+        if reader.read_u32()? != WASM_MAGIC_NUMBER {
+            return Err(parse_error("bad magic number", 0));
+        }
+        if reader.read_u32()? != WASM_VERSION {
+            return Err(parse_error("bad version number", 4));
+        }
+        let mut sections = Vec::new();
+        while !reader.eof() {
+            let section_offset = reader.current_position();
+            let id = reader.read_u8()? as u8;
+            let size = reader.read_var_u32()? as usize;
+            let size_len = reader.current_position() - section_offset - 1;
+            if size > reader.bytes_remaining() {
+                return Err(parse_error("section is out of bounds", section_offset));
+            }
+            let payload = reader.read_bytes(size)?;
+            let (name, data) = if id == 0 {
+                let mut payload_reader = BinaryReader::new(payload);
+                let name = payload_reader.read_string()?;
+                (name.to_vec(), &payload[payload_reader.current_position()..])
+            } else {
+                (Vec::new(), payload)
+            };
+            sections.push(Section {
+                id,
+                name,
+                data: data.to_vec(),
+                size_len,
+            });
+        }
+        Ok(SectionList { sections })
+    }
+
+    pub fn custom_section(&self, name: &str) -> Option<&Section> {
+        self.sections
+            .iter()
+            .find(|section| section.is_custom() && section.name == name.as_bytes())
+    }
+
+    // Removes the custom sections matching the glob pattern and returns them.
+    pub fn remove_custom_sections(&mut self, pattern: &str) -> Vec<Section> {
+        self.remove_custom_sections_if(|name| glob_match(pattern.as_bytes(), name))
+    }
+
+    pub fn remove_custom_sections_if<F: Fn(&[u8]) -> bool>(
+        &mut self,
+        predicate: F,
+    ) -> Vec<Section> {
+        let (removed, kept) = self
+            .sections
+            .drain(..)
+            .partition(|section| section.is_custom() && predicate(&section.name));
+        self.sections = kept;
+        removed
+    }
+
+    // Removes the custom sections selected by the strip options together
+    // with the relocations of these sections. The kept linking and
+    // relocation sections are updated to the new section indices, so the
+    // objects stay linkable.
+    pub fn strip(&mut self, options: &StripOptions) -> Result<StripReport> {
+        let size_before = self.encoded_size();
+        let mut removed: Vec<bool> = self
+            .sections
+            .iter()
+            .map(|section| section.is_custom() && options.removes(&section.name))
+            .collect();
+        for (i, section) in self.sections.iter().enumerate() {
+            if removed[i] || !section.is_custom() || !is_reloc_section_name(&section.name) {
+                continue;
+            }
+            let target = BinaryReader::new(&section.data).read_var_u32()? as usize;
+            if removed.get(target) == Some(&true) {
+                removed[i] = true;
+            }
+        }
+        if !removed.contains(&true) {
+            return Ok(StripReport::default());
+        }
+
+        let mut section_map = Vec::new();
+        let mut new_index = 0;
+        for &is_removed in removed.iter() {
+            section_map.push(if is_removed { None } else { Some(new_index) });
+            if !is_removed {
+                new_index += 1;
+            }
+        }
+        self.remap_section_indices(&section_map)?;
+
+        let mut report = StripReport::default();
+        let sections = self.sections.drain(..).zip(removed).collect::<Vec<_>>();
+        for (section, is_removed) in sections {
+            if is_removed {
+                let mut encoded = Vec::new();
+                section.encode(&mut encoded);
+                report.removed.push(RemovedSection {
+                    name: String::from_utf8_lossy(&section.name).into_owned(),
+                    size: encoded.len(),
+                });
+            } else {
+                self.sections.push(section);
+            }
+        }
+        report.bytes_saved = size_before.saturating_sub(self.encoded_size());
+        Ok(report)
+    }
+
+    // Updates the kept linking and relocation sections to the new section
+    // indices, the removed sections are mapped to None.
+    fn remap_section_indices(&mut self, section_map: &[Option<u32>]) -> Result<()> {
+        let mut symbol_map = None;
+        for (i, section) in self.sections.iter_mut().enumerate() {
+            if section_map[i].is_some()
+                && section.is_custom()
+                && is_linking_section_name(&section.name)
             {
-                if !skipping_section {
-                    write
-                        .write(&wasm[last_written..offset])
-                        .expect("wasm result written");
-                    skipping_section = true;
+                let (linking, map) = remap_linking_sections(&section.data, section_map)?;
+                section.data = linking;
+                symbol_map = Some(map);
+            }
+        }
+        if let Some(ref symbol_map) = symbol_map {
+            for (i, section) in self.sections.iter_mut().enumerate() {
+                if section_map[i].is_none()
+                    || !section.is_custom()
+                    || !is_reloc_section_name(&section.name)
+                {
+                    continue;
+                }
+                if let Some(data) = remap_reloc_section(&section.data, section_map, symbol_map)? {
+                    section.data = data;
                 }
-                input = ParserInput::ReadSectionRawData;
             }
-            ParserState::BeginSection { .. } => {
-                skipping_section = false;
-                input = ParserInput::ReadSectionRawData;
+        }
+        Ok(())
+    }
+
+    // Replaces the DWARF sections with the rewritten ones, which are placed
+    // where the first DWARF section was. The dropped sections are reported.
+    pub fn replace_debug_sections(&mut self, tables: &HashMap<Vec<u8>, Vec<u8>>) -> StripReport {
+        let size_before = self.encoded_size();
+        let position = self
+            .sections
+            .iter()
+            .position(|section| section.is_custom() && is_debug_section_name(&section.name))
+            .unwrap_or(self.sections.len());
+        let mut report = StripReport::default();
+        for section in self.remove_custom_sections_if(is_debug_section_name) {
+            if !tables.contains_key(&section.name) {
+                let mut encoded = Vec::new();
+                section.encode(&mut encoded);
+                report.removed.push(RemovedSection {
+                    name: String::from_utf8_lossy(&section.name).into_owned(),
+                    size: encoded.len(),
+                });
             }
-            ParserState::SectionRawData(..) => {
-                input = ParserInput::Default;
+        }
+        let mut names: Vec<&Vec<u8>> = tables.keys().collect();
+        names.sort();
+        let position = position.min(self.sections.len());
+        for (i, name) in names.into_iter().enumerate() {
+            let section = Section {
+                id: 0,
+                name: name.clone(),
+                data: tables[name].clone(),
+                size_len: 1,
+            };
+            self.sections.insert(position + i, section);
+        }
+        report.bytes_saved = size_before.saturating_sub(self.encoded_size());
+        report
+    }
+
+    fn encoded_size(&self) -> usize {
+        let mut size = 0;
+        for section in self.sections.iter() {
+            let mut encoded = Vec::new();
+            section.encode(&mut encoded);
+            size += encoded.len();
+        }
+        size
+    }
+
+    // Appends the custom section, the sections with the same name are kept.
+    pub fn add_custom_section(&mut self, name: &str, data: Vec<u8>) {
+        self.sections.push(Section::custom(name, data));
+    }
+
+    // Replaces the first custom section with the name and removes its
+    // duplicates, or appends the section if there is none.
+    pub fn set_custom_section(&mut self, name: &str, data: Vec<u8>) {
+        let position = self
+            .sections
+            .iter()
+            .position(|section| section.is_custom() && section.name == name.as_bytes());
+        match position {
+            Some(position) => {
+                self.sections[position].data = data;
+                let mut i = 0;
+                self.sections.retain(|section| {
+                    i += 1;
+                    i - 1 <= position || !section.is_custom() || section.name != name.as_bytes()
+                });
+            }
+            None => self.add_custom_section(name, data),
+        }
+    }
+
+    // Moves the custom sections matching the glob pattern before or after
+    // the named section, or to the end of the module, keeping their order.
+    // The relocations of the moved sections follow them, and the linking
+    // and relocation sections are updated to the new section indices.
+    pub fn move_custom_sections(
+        &mut self,
+        pattern: &str,
+        position: &SectionPosition,
+    ) -> Result<()> {
+        let mut is_moved: Vec<bool> = self
+            .sections
+            .iter()
+            .map(|section| section.is_custom() && glob_match(pattern.as_bytes(), &section.name))
+            .collect();
+        for (i, section) in self.sections.iter().enumerate() {
+            if !section.is_custom() || !is_reloc_section_name(&section.name) {
+                continue;
             }
-            ParserState::EndSection => {
-                if skipping_section {
-                    last_written = offset;
+            let target = BinaryReader::new(&section.data).read_var_u32()? as usize;
+            if is_moved.get(target) == Some(&true) {
+                is_moved[i] = true;
+            }
+        }
+        let (moved, mut order): (Vec<usize>, Vec<usize>) =
+            (0..self.sections.len()).partition(|&i| is_moved[i]);
+        let insert_position = match *position {
+            SectionPosition::Before(ref name) | SectionPosition::After(ref name) => {
+                let anchor = match order.iter().position(|&i| self.sections[i].has_name(name)) {
+                    Some(anchor) => anchor,
+                    None => {
+                        return Err(Error::InvalidArgument(format!(
+                            "section {} to move the sections to was not found",
+                            name
+                        )))
+                    }
+                };
+                match *position {
+                    SectionPosition::After(_) => anchor + 1,
+                    _ => anchor,
                 }
             }
-            _ => {}
+            SectionPosition::End => order.len(),
+        };
+        order.splice(insert_position..insert_position, moved);
+
+        let mut section_map = vec![None; order.len()];
+        for (new_index, &old_index) in order.iter().enumerate() {
+            section_map[old_index] = Some(new_index as u32);
+        }
+        // The linker reads the relocations after the sections they apply to.
+        for (i, section) in self.sections.iter().enumerate() {
+            if !section.is_custom() || !is_reloc_section_name(&section.name) {
+                continue;
+            }
+            let target = BinaryReader::new(&section.data).read_var_u32()? as usize;
+            if target < section_map.len() && section_map[target] > section_map[i] {
+                return Err(Error::InvalidArgument(format!(
+                    "section {} cannot be moved before the section it relocates",
+                    String::from_utf8_lossy(&section.name)
+                )));
+            }
         }
+        self.remap_section_indices(&section_map)?;
+        let mut sections: Vec<Option<Section>> = self.sections.drain(..).map(Some).collect();
+        for i in order {
+            self.sections.extend(sections[i].take());
+        }
+        Ok(())
+    }
+
+    pub fn set_source_mapping_url(&mut self, url: &str) {
+        let mut data = Vec::new();
+        write_string(&mut data, url.as_bytes());
+        self.set_custom_section("sourceMappingURL", data);
+    }
+
+    pub fn set_external_debug_info(&mut self, url: &str) {
+        let mut data = Vec::new();
+        write_string(&mut data, url.as_bytes());
+        self.set_custom_section("external_debug_info", data);
+    }
+
+    // Adds the tool to the producers section field, e.g. "processed-by", the
+    // version of the tool already listed is replaced.
+    pub fn add_producer(&mut self, field: &str, name: &str, version: &str) -> Result<()> {
+        let mut fields = match self.custom_section("producers") {
+            Some(section) => read_producers(&section.data)?,
+            None => Vec::new(),
+        };
+        let position = match fields.iter().position(|f| f.0 == field.as_bytes()) {
+            Some(position) => position,
+            None => {
+                fields.push((field.as_bytes().to_vec(), Vec::new()));
+                fields.len() - 1
+            }
+        };
+        let values = &mut fields[position].1;
+        let value = (name.as_bytes().to_vec(), version.as_bytes().to_vec());
+        match values.iter().position(|v| v.0 == value.0) {
+            Some(i) => values[i] = value,
+            None => values.push(value),
+        }
+        let mut data = Vec::new();
+        write_var_u32(&mut data, fields.len());
+        for (field, values) in fields.iter() {
+            write_string(&mut data, field);
+            write_var_u32(&mut data, values.len());
+            for (name, version) in values.iter() {
+                write_string(&mut data, name);
+                write_string(&mut data, version);
+            }
+        }
+        self.set_custom_section("producers", data);
+        Ok(())
     }
-    if !skipping_section && last_written < wasm.len() {
-        write
-            .write(&wasm[last_written..wasm.len()])
-            .expect("wasm result written");
+
+    // Sets the module name subsection of the name section, the function
+    // and local names are kept.
+    pub fn set_module_name(&mut self, module_name: &str) -> Result<()> {
+        let mut data = Vec::new();
+        let mut subsection = Vec::new();
+        write_string(&mut subsection, module_name.as_bytes());
+        data.push(0);
+        write_string(&mut data, &subsection);
+        if let Some(section) = self.custom_section("name") {
+            let mut reader = BinaryReader::new(&section.data);
+            while !reader.eof() {
+                let start = reader.current_position();
+                let id = reader.read_u8()?;
+                reader.read_string()?;
+                if id != 0 {
+                    data.extend_from_slice(&section.data[start..reader.current_position()]);
+                }
+            }
+        }
+        self.set_custom_section("name", data);
+        Ok(())
+    }
+
+    // Sets the build_id section, as produced by the linker --build-id.
+    pub fn set_build_id(&mut self, build_id: &[u8]) {
+        let mut data = Vec::new();
+        write_string(&mut data, build_id);
+        self.set_custom_section("build_id", data);
+    }
+
+    // Encodes the module and verifies that the result can be parsed back.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut wasm = Vec::new();
+        wasm.extend_from_slice(&WASM_MAGIC_NUMBER.to_le_bytes());
+        wasm.extend_from_slice(&WASM_VERSION.to_le_bytes());
+        for (i, section) in self.sections.iter().enumerate() {
+            let section_offset = wasm.len();
+            section.encode(&mut wasm);
+            if section.is_custom() && is_reloc_section_name(&section.name) {
+                let target = BinaryReader::new(&section.data).read_var_u32()? as usize;
+                if target >= i {
+                    return Err(parse_error(
+                        "relocation section precedes the section it relocates",
+                        section_offset,
+                    ));
+                }
+            }
+        }
+        let parsed = SectionList::parse(&wasm)?;
+        if parsed.sections.len() != self.sections.len() {
+            return Err(parse_error("rewritten module sections mismatch", 8));
+        }
+        Ok(wasm)
     }
 }
 
-fn convert_to_leb(n: usize) -> Vec<u8> {
-    let mut buf = Vec::new();
-    let mut i = n;
-    while i > 128 {
-        buf.push(0x80 | (n & 0x7f) as u8);
-        i = i >> 7;
+// Creates the debug file for the stripped module: the DWARF sections of the
+// original module and the stripped module code layout, so the stripped
+// module offsets can be symbolicated.
+pub fn create_debug_file(wasm: &[u8], stripped_wasm: &[u8]) -> Result<Vec<u8>> {
+    let stripped = DebugSections::read_sections(stripped_wasm)?;
+    let mut sections = SectionList::parse(wasm)?;
+    sections
+        .sections
+        .retain(|section| section.is_custom() && is_debug_section_name(&section.name));
+    let mut data = Vec::new();
+    write_var_u32(&mut data, CODE_LAYOUT_VERSION as usize);
+    write_var_u32(&mut data, stripped.code_content);
+    write_var_u32(&mut data, stripped.code_size);
+    write_var_u32(&mut data, stripped.func_offsets.len());
+    for &func_offset in stripped.func_offsets.iter() {
+        write_var_u32(&mut data, func_offset);
+    }
+    sections.add_custom_section(CODE_LAYOUT_SECTION_NAME, data);
+    sections.encode()
+}
+
+// Removes the DWARF sections, see StripProfile::DebugOnly.
+pub fn remove_debug_sections(wasm: &[u8], write: &mut dyn Write) -> Result<()> {
+    let mut sections = SectionList::parse(wasm)?;
+    sections.strip(&StripOptions {
+        profile: Some(StripProfile::DebugOnly),
+        ..StripOptions::default()
+    })?;
+    write.write_all(&sections.encode()?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(sections: &[&[u8]]) -> Vec<u8> {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        for section in sections {
+            wasm.extend_from_slice(section);
+        }
+        wasm
+    }
+
+    fn custom_section(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut section = Vec::new();
+        Section::custom(name, data.to_vec()).encode(&mut section);
+        section
+    }
+
+    fn section_names(sections: &SectionList) -> Vec<String> {
+        sections
+            .sections
+            .iter()
+            .map(|section| match section.id {
+                0 => String::from_utf8_lossy(&section.name).into_owned(),
+                id => String::from_utf8_lossy(known_section_name(id as u32)).into_owned(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn var_u32() {
+        let encode = |n| {
+            let mut buf = Vec::new();
+            write_var_u32(&mut buf, n);
+            buf
+        };
+        assert_eq!(encode(0), [0x00]);
+        assert_eq!(encode(127), [0x7f]);
+        assert_eq!(encode(128), [0x80, 0x01]);
+        assert_eq!(encode(16383), [0xff, 0x7f]);
+        assert_eq!(encode(16384), [0x80, 0x80, 0x01]);
+        assert_eq!(encode(0xffff_ffff), [0xff, 0xff, 0xff, 0xff, 0x0f]);
+    }
+
+    #[test]
+    fn padded_var_u32() {
+        let encode = |n, min_len| {
+            let mut buf = Vec::new();
+            write_padded_var_u32(&mut buf, n, min_len);
+            buf
+        };
+        assert_eq!(encode(0, 5), [0x80, 0x80, 0x80, 0x80, 0x00]);
+        assert_eq!(encode(127, 5), [0xff, 0x80, 0x80, 0x80, 0x00]);
+        assert_eq!(encode(128, 5), [0x80, 0x81, 0x80, 0x80, 0x00]);
+        // The values that do not fit are not truncated.
+        assert_eq!(encode(128, 1), [0x80, 0x01]);
+        let mut reader = BinaryReader::new(&[0x80, 0x81, 0x80, 0x80, 0x00]);
+        assert_eq!(reader.read_var_u32().unwrap(), 128);
+    }
+
+    #[test]
+    fn var_i64() {
+        let encode = |n| {
+            let mut buf = Vec::new();
+            write_var_i64(&mut buf, n);
+            buf
+        };
+        assert_eq!(encode(0), [0x00]);
+        assert_eq!(encode(63), [0x3f]);
+        assert_eq!(encode(64), [0xc0, 0x00]);
+        assert_eq!(encode(127), [0xff, 0x00]);
+        assert_eq!(encode(128), [0x80, 0x01]);
+        assert_eq!(encode(-1), [0x7f]);
+        assert_eq!(encode(-64), [0x40]);
+        assert_eq!(encode(-65), [0xbf, 0x7f]);
+        assert_eq!(encode(-128), [0x80, 0x7f]);
+        assert_eq!(
+            encode(i64::MIN),
+            [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f]
+        );
+        for &n in [i64::MIN, i64::MAX, -129, 129].iter() {
+            let buf = encode(n);
+            let mut reader = gimli::EndianSlice::new(&buf, gimli::LittleEndian);
+            assert_eq!(gimli::leb128::read::signed(&mut reader).unwrap(), n);
+        }
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_match(b".debug_*", b".debug_info"));
+        assert!(glob_match(b".debug_*", b".debug_"));
+        assert!(!glob_match(b".debug_*", b"reloc..debug_info"));
+        assert!(glob_match(b"*debug*", b"reloc..debug_info"));
+        assert!(glob_match(b"reloc.?ODE", b"reloc.CODE"));
+        assert!(!glob_match(b"reloc.?ODE", b"reloc.ODE"));
+        assert!(glob_match(b"name", b"name"));
+        assert!(!glob_match(b"name", b"names"));
+        assert!(glob_match(b"*", b""));
+        assert!(!glob_match(b"", b"name"));
+    }
+
+    #[test]
+    fn section_list_round_trip() {
+        // The type section size is padded to 5 bytes.
+        let wasm = module(&[
+            &[1, 0x84, 0x80, 0x80, 0x80, 0x00, 1, 0x60, 0, 0],
+            &custom_section("producers", &[0]),
+            &custom_section(&"x".repeat(200), &[1, 2, 3]),
+        ]);
+        let sections = SectionList::parse(&wasm).unwrap();
+        assert_eq!(sections.sections.len(), 3);
+        assert_eq!(sections.custom_section("producers").unwrap().data, [0]);
+        assert_eq!(sections.encode().unwrap(), wasm);
+    }
+
+    #[test]
+    fn set_custom_section() {
+        let wasm = module(&[
+            &custom_section("sourceMappingURL", b"\x01a"),
+            &custom_section("sourceMappingURL", b"\x01b"),
+        ]);
+        let mut sections = SectionList::parse(&wasm).unwrap();
+        sections.set_source_mapping_url(&"u".repeat(300));
+        assert_eq!(section_names(&sections), ["sourceMappingURL"]);
+        let wasm = sections.encode().unwrap();
+        let debug_sections = DebugSections::read_sections(&wasm).unwrap();
+        assert_eq!(debug_sections.source_mapping_url, Some("u".repeat(300)));
+    }
+
+    #[test]
+    fn move_custom_sections() {
+        let wasm = module(&[
+            &[1, 4, 1, 0x60, 0, 0],
+            &custom_section("a", &[]),
+            &custom_section("b", &[]),
+            &custom_section("c", &[]),
+        ]);
+        let mut sections = SectionList::parse(&wasm).unwrap();
+        sections
+            .move_custom_sections("a", &SectionPosition::End)
+            .unwrap();
+        assert_eq!(section_names(&sections), ["TYPE", "b", "c", "a"]);
+        sections
+            .move_custom_sections("?", &SectionPosition::Before(String::from("TYPE")))
+            .unwrap();
+        assert_eq!(section_names(&sections), ["b", "c", "a", "TYPE"]);
+        sections
+            .move_custom_sections("b", &SectionPosition::After(String::from("a")))
+            .unwrap();
+        assert_eq!(section_names(&sections), ["c", "a", "b", "TYPE"]);
+        assert!(sections
+            .move_custom_sections("b", &SectionPosition::After(String::from("b")))
+            .is_err());
+    }
+
+    #[test]
+    fn move_relocations() {
+        let mut sections = SectionList::parse(&object(2)).unwrap();
+        sections
+            .move_custom_sections("foo", &SectionPosition::End)
+            .unwrap();
+        assert_eq!(
+            section_names(&sections),
+            [".debug_info", ".debug_str", "linking", "foo", "reloc.foo"]
+        );
+        let reloc = &sections.custom_section("reloc.foo").unwrap().data;
+        assert_eq!(reloc[..], [3, 1, 9, 0, 1, 4]);
+        sections.encode().unwrap();
+
+        let mut sections = SectionList::parse(&object(2)).unwrap();
+        let position = SectionPosition::Before(String::from("foo"));
+        assert!(sections.move_custom_sections("reloc.*", &position).is_err());
+        sections.sections.swap(2, 4);
+        assert!(sections.encode().is_err());
+    }
+
+    // Relocatable object with the section symbols of the custom sections
+    // 0 and 2, and the relocation of the section 2 that refers to the
+    // symbol of the section `symbol_section`.
+    fn object(symbol_section: u8) -> Vec<u8> {
+        let symbol_table = [2, 3, 0x2, 0, 3, 0x2, 2];
+        let mut linking = vec![2, 8, symbol_table.len() as u8];
+        linking.extend_from_slice(&symbol_table);
+        let symbol = if symbol_section == 0 { 0 } else { 1 };
+        // R_WASM_SECTION_OFFSET_I32 at 0 with the addend 4.
+        let reloc = [2, 1, 9, 0, symbol, 4];
+        module(&[
+            &custom_section(".debug_info", &[0; 4]),
+            &custom_section(".debug_str", b"main\0"),
+            &custom_section("foo", &[0; 4]),
+            &custom_section("linking", &linking),
+            &custom_section("reloc.foo", &reloc),
+        ])
+    }
+
+    #[test]
+    fn strip_remaps_sections() {
+        let mut sections = SectionList::parse(&object(2)).unwrap();
+        let report = sections
+            .strip(&StripOptions {
+                profile: Some(StripProfile::DebugOnly),
+                ..StripOptions::default()
+            })
+            .unwrap();
+        assert_eq!(section_names(&sections), ["foo", "linking", "reloc.foo"]);
+        let removed: Vec<&str> = report.removed.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(removed, [".debug_info", ".debug_str"]);
+        // The removed sections and the removed symbol.
+        assert_eq!(report.bytes_saved, 18 + 18 + 3);
+        // The symbol of the removed section is dropped, the section index of
+        // the other one is updated.
+        let linking = &sections.custom_section("linking").unwrap().data;
+        assert_eq!(linking[..], [2, 8, 4, 1, 3, 0x2, 0]);
+        let reloc = &sections.custom_section("reloc.foo").unwrap().data;
+        assert_eq!(reloc[..], [0, 1, 9, 0, 0, 4]);
+    }
+
+    #[test]
+    fn strip_keeps_referenced_sections() {
+        let mut sections = SectionList::parse(&object(0)).unwrap();
+        let options = StripOptions {
+            profile: Some(StripProfile::DebugOnly),
+            ..StripOptions::default()
+        };
+        assert!(sections.strip(&options).is_err());
+
+        let mut sections = SectionList::parse(&object(0)).unwrap();
+        let report = sections
+            .strip(&StripOptions {
+                remove: vec![String::from(".debug_str")],
+                ..StripOptions::default()
+            })
+            .unwrap();
+        assert_eq!(report.removed.len(), 1);
+        let linking = &sections.custom_section("linking").unwrap().data;
+        assert_eq!(linking[..], [2, 8, 7, 2, 3, 0x2, 0, 3, 0x2, 1]);
+        let reloc = &sections.custom_section("reloc.foo").unwrap().data;
+        assert_eq!(reloc[..], [1, 1, 9, 0, 0, 4]);
     }
-    buf.push(i as u8);
-    buf
-}
-
-pub fn add_source_mapping_url_section(url: &str, write: &mut Write) {
-    let name = b"sourceMappingURL";
-    let mut result = Vec::new();
-    let custom_section_id = convert_to_leb(0);
-    result.extend_from_slice(&custom_section_id);
-    let name_size = convert_to_leb(name.len());
-    let url_size = convert_to_leb(url.len());
-    let payload_size = convert_to_leb(name_size.len() + name.len() + url_size.len() + url.len());
-    result.extend_from_slice(&payload_size);
-    result.extend_from_slice(&name_size);
-    result.extend_from_slice(name);
-    result.extend_from_slice(&url_size);
-    result.extend_from_slice(url.as_bytes());
-    write.write(&result).expect("wasm result written");
 }